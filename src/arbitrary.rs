@@ -0,0 +1,53 @@
+//! Proptest strategies generating random transaction sequences
+
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::{ClientId, Transaction};
+
+/// A random transaction
+///
+/// Client and transaction ids are drawn from small ranges so that disputes, resolves and
+/// chargebacks often refer to earlier transactions, and ids are reused across clients.
+pub(crate) fn transaction() -> impl Strategy<Value = Transaction> {
+    let client = 0..4 as ClientId;
+    let tx = 0..32u32;
+    let amount = (0..100_000i64).prop_map(|n| Decimal::new(n, 2));
+    prop_oneof![
+        3 => (client.clone(), tx.clone(), amount.clone())
+            .prop_map(|(id, tx, amount)| Transaction::Deposit(id, tx, amount)),
+        2 => (client.clone(), tx.clone(), amount)
+            .prop_map(|(id, tx, amount)| Transaction::Withdrawal(id, tx, amount)),
+        2 => (client.clone(), tx.clone()).prop_map(|(id, tx)| Transaction::Dispute(id, tx)),
+        1 => (client.clone(), tx.clone()).prop_map(|(id, tx)| Transaction::Resolve(id, tx)),
+        1 => (client.clone(), tx.clone()).prop_map(|(id, tx)| Transaction::Chargeback(id, tx)),
+        1 => (client.clone(), tx).prop_map(|(id, tx)| Transaction::Settle(id, tx)),
+        1 => client.clone().prop_map(Transaction::Freeze),
+        1 => client.prop_map(Transaction::Unfreeze),
+    ]
+}
+
+/// A random sequence of up to `max_len` transactions
+pub(crate) fn transactions(max_len: usize) -> impl Strategy<Value = Vec<Transaction>> {
+    prop::collection::vec(transaction(), 0..max_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::arbitrary::transactions;
+    use crate::*;
+
+    proptest! {
+        #[test]
+        fn invariants_hold(txs in transactions(64)) {
+            let mut engine = Engine::new();
+            for tx in txs {
+                engine.handle_record(tx);
+                prop_assert!(engine.verify_invariants(), "inconsistent balances after {}", tx);
+                prop_assert!(engine.clients().all(|client| client.held >= Decimal::ZERO));
+            }
+        }
+    }
+}
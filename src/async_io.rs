@@ -0,0 +1,143 @@
+//! Async csv input for tokio based pipelines, enabled by the `async` feature
+
+use tokio::io::AsyncRead;
+
+use crate::csv_io::{check_columns, split_amount, Row, TimeOrder};
+use crate::{Engine, EngineError, ProcessStats};
+
+impl Engine {
+    /// Read csv records from an async reader
+    ///
+    /// Reading is asynchronous while parsing and applying each record happens synchronously, with
+    /// the same handling of bad records as `from_reader`. If enabled with
+    /// `EngineBuilder::order_by_time`, all records are read first and then applied in the order of
+    /// their timestamp.
+    pub async fn from_async_reader<R: AsyncRead + Unpin + Send>(
+        &mut self,
+        reader: R,
+    ) -> Result<ProcessStats, EngineError> {
+        let mut stats = ProcessStats::default();
+        let mut csv_reader = csv_async::AsyncReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .trim(csv_async::Trim::All)
+            .create_reader(reader);
+        let mut headers = None;
+        if self.has_headers {
+            let mapped: csv_async::StringRecord = self.map_headers(csv_reader.headers().await?);
+            check_columns(&mapped)?;
+            csv_reader.set_headers(mapped.clone());
+            headers = Some(mapped);
+        }
+        let mut record = csv_async::StringRecord::new();
+        let mut timed = TimeOrder::default();
+        loop {
+            let row = match csv_reader.read_record(&mut record).await {
+                Ok(false) => break,
+                Ok(true) => match split_amount(&record, headers.as_ref()) {
+                    Some(err) => Err(err.into()),
+                    None => record
+                        .deserialize::<Row>(headers.as_ref())
+                        .map_err(Into::into),
+                },
+                Err(err) => Err(err.into()),
+            };
+            let row = match row {
+                Ok(row) => row,
+                Err(EngineError::AsyncCsv(err)) if self.skip_csv_errors && !err.is_io_error() => {
+                    log::warn!("Skipping malformed record: {}", err);
+                    stats.errors += 1;
+                    continue;
+                }
+                Err(err @ EngineError::Parse(_)) => {
+                    self.skip_record(err, &mut stats)?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if row.is_blank() {
+                continue;
+            }
+            if self.order_by_time {
+                timed.push(self, row, &mut stats)?;
+            } else {
+                self.read_record(&row.into_record(), &mut stats)?;
+            }
+        }
+        for record in timed.into_sorted() {
+            self.read_record(&record, &mut stats)?;
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn from_async_reader() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, 1, x, 1.0
+withdrawal, 1, 2, 0.5
+dispute, 1, 1,";
+
+        let stats = engine.from_async_reader(csv.as_bytes()).await.unwrap();
+        assert_eq!(
+            stats,
+            ProcessStats {
+                applied: 3,
+                skipped: 1,
+                errors: 0,
+            }
+        );
+        assert!(engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().available == dec!(-0.5));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(1.5));
+    }
+
+    #[tokio::test]
+    async fn from_async_reader_byte_order_mark() {
+        let mut engine = Engine::new();
+
+        let csv = "\u{feff}type, client, tx, amount
+deposit, 1, 1, 2.0";
+
+        let stats = engine.from_async_reader(csv.as_bytes()).await.unwrap();
+        assert_eq!(stats.applied, 1);
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[tokio::test]
+    async fn from_async_reader_unquoted_thousands_separators() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit,1,1,1,000.00
+deposit,1,2,5.0";
+
+        let stats = engine.from_async_reader(csv.as_bytes()).await.unwrap();
+        assert_eq!(stats.applied, 1);
+        assert_eq!(stats.skipped, 1);
+        assert!(engine.client(1).unwrap().total == dec!(5.0));
+    }
+
+    #[tokio::test]
+    async fn from_async_reader_order_by_time() {
+        let mut engine = Engine::builder().order_by_time(true).build();
+
+        let csv = "type, client, tx, amount, timestamp
+withdrawal, 1, 2, 1.0, 2024-01-02T00:00:00Z
+deposit, 1, 1, 3.0, 2024-01-01T00:00:00Z";
+
+        let stats = engine.from_async_reader(csv.as_bytes()).await.unwrap();
+        assert_eq!(stats.applied, 2);
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+}
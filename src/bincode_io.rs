@@ -0,0 +1,107 @@
+//! Compact binary client dumps, enabled by the `bincode` feature
+
+use std::io::{self, Read, Write};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{Client, ClientId, Engine, TransactionCounts};
+
+/// A client as written by `Engine::write_clients_bincode`
+///
+/// Amounts are stored in `Decimal`'s 16 byte binary representation, which is exact and does not
+/// need the self-describing format that the serde implementation of `Decimal` relies on.
+#[derive(Serialize, Deserialize)]
+struct BinaryClient {
+    id: ClientId,
+    available: [u8; 16],
+    held: [u8; 16],
+    total: [u8; 16],
+    locked: bool,
+    counts: TransactionCounts,
+    scale: Option<u32>,
+}
+
+impl From<&Client> for BinaryClient {
+    fn from(client: &Client) -> Self {
+        Self {
+            id: client.id,
+            available: client.available.serialize(),
+            held: client.held.serialize(),
+            total: client.total.serialize(),
+            locked: client.locked,
+            counts: client.counts,
+            scale: client.scale,
+        }
+    }
+}
+
+impl From<BinaryClient> for Client {
+    fn from(client: BinaryClient) -> Self {
+        Self {
+            id: client.id,
+            available: Decimal::deserialize(client.available),
+            held: Decimal::deserialize(client.held),
+            total: Decimal::deserialize(client.total),
+            locked: client.locked,
+            counts: client.counts,
+            scale: client.scale,
+        }
+    }
+}
+
+impl Engine {
+    /// Write the client list in bincode's binary format, in ascending id order
+    ///
+    /// The dump can be read back with `read_clients_bincode`.
+    pub fn write_clients_bincode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let clients: Vec<BinaryClient> = self.clients().map(|c| BinaryClient::from(&c)).collect();
+        bincode::serde::encode_into_std_write(&clients, w, bincode::config::standard())
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    /// Read a client list written by `write_clients_bincode`
+    ///
+    /// The clients can be loaded into an engine with `Engine::with_clients`.
+    pub fn read_clients_bincode<R: Read>(r: &mut R) -> io::Result<Vec<Client>> {
+        let clients: Vec<BinaryClient> =
+            bincode::serde::decode_from_std_read(r, bincode::config::standard())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(clients.into_iter().map(Client::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn bincode_round_trip() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(2, 1, dec!(1.2345)),
+            Transaction::Deposit(1, 2, dec!(10.0)),
+            Transaction::Withdrawal(1, 3, dec!(2.5)),
+            Transaction::Dispute(2, 1),
+            Transaction::Deposit(3, 4, dec!(4.0)),
+            Transaction::Dispute(3, 4),
+            Transaction::Chargeback(3, 4),
+        ]);
+
+        let mut out = Vec::new();
+        engine.write_clients_bincode(&mut out).unwrap();
+        let clients = Engine::read_clients_bincode(&mut out.as_slice()).unwrap();
+
+        assert_eq!(
+            format!("{:?}", clients),
+            format!("{:?}", engine.clients().collect::<Vec<_>>())
+        );
+        assert!(clients[1].held == dec!(1.2345));
+        assert!(clients[2].locked);
+
+        let res = Engine::read_clients_bincode(&mut &out[..out.len() - 1]);
+        assert!(res.is_err());
+    }
+}
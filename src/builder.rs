@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Engine, OverdraftPolicy, DECIMAL_PLACES};
+
+/// Builder for configuring an `Engine`
+#[derive(Clone, Debug)]
+pub struct EngineBuilder {
+    #[cfg(feature = "csv")]
+    strict: bool,
+    expected_clients: usize,
+    precision: Option<u32>,
+    warn_out_of_order: bool,
+    allow_withdrawal_disputes: bool,
+    fixed_client_scale: bool,
+    max_open_disputes: Option<usize>,
+    overdraft_policy: OverdraftPolicy,
+    track_history: bool,
+    #[cfg(feature = "csv")]
+    delimiter: u8,
+    #[cfg(feature = "csv")]
+    has_headers: bool,
+    #[cfg(feature = "csv")]
+    thousands_separators: bool,
+    #[cfg(feature = "csv")]
+    max_transactions: Option<usize>,
+    #[cfg(feature = "csv")]
+    order_by_time: bool,
+    #[cfg(feature = "csv")]
+    skip_csv_errors: bool,
+    #[cfg(feature = "csv")]
+    column_names: HashMap<String, String>,
+    scale: u32,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "csv")]
+            strict: false,
+            expected_clients: 0,
+            precision: None,
+            warn_out_of_order: false,
+            allow_withdrawal_disputes: true,
+            fixed_client_scale: false,
+            max_open_disputes: None,
+            overdraft_policy: OverdraftPolicy::Reject,
+            track_history: false,
+            #[cfg(feature = "csv")]
+            delimiter: b',',
+            #[cfg(feature = "csv")]
+            has_headers: true,
+            #[cfg(feature = "csv")]
+            thousands_separators: false,
+            #[cfg(feature = "csv")]
+            max_transactions: None,
+            #[cfg(feature = "csv")]
+            order_by_time: false,
+            #[cfg(feature = "csv")]
+            skip_csv_errors: false,
+            #[cfg(feature = "csv")]
+            column_names: HashMap::new(),
+            scale: DECIMAL_PLACES,
+        }
+    }
+}
+
+impl EngineBuilder {
+    /// Create a builder with the default configuration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return an error on records that can not be parsed instead of skipping them
+    ///
+    /// This includes records with an unknown transaction type, which are otherwise logged and
+    /// skipped, and dispute, resolve, chargeback and settle records with an amount, whose amount is
+    /// otherwise ignored.
+    #[cfg(feature = "csv")]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Pre-size the client map for the expected number of clients
+    pub fn expected_clients(mut self, expected_clients: usize) -> Self {
+        self.expected_clients = expected_clients;
+        self
+    }
+
+    /// Number of decimal places used when writing client balances
+    ///
+    /// Defaults to the scale, `DECIMAL_PLACES` unless configured with `scale`.
+    pub fn precision(mut self, precision: u32) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Apply csv records in the order of their `timestamp` column instead of the input order
+    ///
+    /// Timestamps are ISO 8601 date and times such as `2024-01-31T12:00:00Z`, without an offset
+    /// they are taken as UTC. Records without a timestamp are applied first. This applies to
+    /// `from_reader` and the functions built on it as well as `from_async_reader` and
+    /// `from_reader_parallel`, which then have to buffer all records in memory before applying any
+    /// of them, instead of streaming the input.
+    #[cfg(feature = "csv")]
+    pub fn order_by_time(mut self, order_by_time: bool) -> Self {
+        self.order_by_time = order_by_time;
+        self
+    }
+
+    /// Log and skip records that the csv reader fails on, such as invalid UTF-8, instead of
+    /// aborting
+    ///
+    /// Skipped records are counted in `ProcessStats::errors`. Io errors of the underlying reader
+    /// still abort reading, as the reader can not be resumed reliably after them.
+    #[cfg(feature = "csv")]
+    pub fn skip_csv_errors(mut self, skip_csv_errors: bool) -> Self {
+        self.skip_csv_errors = skip_csv_errors;
+        self
+    }
+
+    /// Read the csv column `column`, such as `type`, from the header field named `name`
+    ///
+    /// For input from systems using other column names, e.g. `column_name("type", "action")`.
+    /// Without a header the columns are read by position and the names are ignored.
+    #[cfg(feature = "csv")]
+    pub fn column_name(mut self, column: &str, name: &str) -> Self {
+        self.column_names
+            .insert(name.to_string(), column.to_string());
+        self
+    }
+
+    /// Number of decimal places amounts are parsed and written with, e.g. 8 for crypto assets
+    ///
+    /// Amounts with more decimal places are rejected. Unless set with `precision`, client
+    /// balances are written with the same number of decimal places. Defaults to `DECIMAL_PLACES`.
+    pub fn scale(mut self, scale: u32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Log a warning for deposits and withdrawals with a lower id than an earlier one
+    ///
+    /// Out of order ids often indicate a corrupted or wrongly sorted input. The transactions are
+    /// still processed.
+    pub fn warn_out_of_order(mut self, warn_out_of_order: bool) -> Self {
+        self.warn_out_of_order = warn_out_of_order;
+        self
+    }
+
+    /// Record each client's available funds after every applied transaction
+    ///
+    /// The history is available through `Engine::history`. It is disabled by default, since it
+    /// keeps one entry per transaction in memory.
+    pub fn track_history(mut self, track_history: bool) -> Self {
+        self.track_history = track_history;
+        self
+    }
+
+    /// How to handle withdrawals exceeding the available funds, rejected by default
+    pub fn overdraft_policy(mut self, overdraft_policy: OverdraftPolicy) -> Self {
+        self.overdraft_policy = overdraft_policy;
+        self
+    }
+
+    /// Allow disputing withdrawals, enabled by default
+    ///
+    /// When disabled, disputes of withdrawals are logged and ignored.
+    pub fn allow_withdrawal_disputes(mut self, allow_withdrawal_disputes: bool) -> Self {
+        self.allow_withdrawal_disputes = allow_withdrawal_disputes;
+        self
+    }
+
+    /// Require each client's amounts to have the same number of decimal places, e.g. for
+    /// clients transacting in currencies with different minor units
+    ///
+    /// The scale is taken from the client's first deposit or withdrawal, later ones with a
+    /// different scale are rejected. Disabled by default.
+    pub fn fixed_client_scale(mut self, fixed_client_scale: bool) -> Self {
+        self.fixed_client_scale = fixed_client_scale;
+        self
+    }
+
+    /// Ignore new disputes of a client that already has `max` disputes open, unlimited by default
+    pub fn max_open_disputes_per_client(mut self, max: usize) -> Self {
+        self.max_open_disputes = Some(max);
+        self
+    }
+
+    /// Field delimiter of the csv input, e.g. `b';'` or `b'\t'`
+    #[cfg(feature = "csv")]
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Whether the csv input starts with a header row
+    ///
+    /// Without a header the columns are expected in `type, client, tx, amount` order.
+    #[cfg(feature = "csv")]
+    pub fn has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// Accept amounts with comma thousands separators, such as `"1,000.00"`
+    ///
+    /// With a comma delimiter such amounts have to be quoted. Unquoted amounts are split into
+    /// several fields by the csv reader, and are rejected as invalid whether or not this is
+    /// enabled.
+    #[cfg(feature = "csv")]
+    pub fn thousands_separators(mut self, thousands_separators: bool) -> Self {
+        self.thousands_separators = thousands_separators;
+        self
+    }
+
+    /// Stop reading csv input with an error after `max_transactions` records
+    ///
+    /// The limit applies to each call reading input, such as `read_file`.
+    #[cfg(feature = "csv")]
+    pub fn max_transactions(mut self, max_transactions: usize) -> Self {
+        self.max_transactions = Some(max_transactions);
+        self
+    }
+
+    /// Build the engine
+    pub fn build(self) -> Engine {
+        Engine {
+            transactions: HashMap::new(),
+            clients: HashMap::with_capacity(self.expected_clients),
+            disbutes: HashSet::new(),
+            last_tx: HashMap::new(),
+            precision: self.precision.unwrap_or(self.scale),
+            warn_out_of_order: self.warn_out_of_order,
+            allow_withdrawal_disputes: self.allow_withdrawal_disputes,
+            fixed_client_scale: self.fixed_client_scale,
+            max_open_disputes: self.max_open_disputes,
+            overdraft_policy: self.overdraft_policy,
+            track_history: self.track_history,
+            history: HashMap::new(),
+            totals: Default::default(),
+            max_tx: None,
+            #[cfg(feature = "csv")]
+            strict: self.strict,
+            #[cfg(feature = "csv")]
+            delimiter: self.delimiter,
+            #[cfg(feature = "csv")]
+            has_headers: self.has_headers,
+            #[cfg(feature = "csv")]
+            thousands_separators: self.thousands_separators,
+            #[cfg(feature = "csv")]
+            max_transactions: self.max_transactions,
+            #[cfg(feature = "csv")]
+            order_by_time: self.order_by_time,
+            #[cfg(feature = "csv")]
+            skip_csv_errors: self.skip_csv_errors,
+            #[cfg(feature = "csv")]
+            column_names: self.column_names,
+            #[cfg(feature = "csv")]
+            scale: self.scale,
+            audit: Default::default(),
+            callback: Default::default(),
+        }
+    }
+}
@@ -0,0 +1,2110 @@
+//! Csv input and output, enabled by the `csv` feature
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::num::{IntErrorKind, ParseIntError};
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use csv::{ByteRecord, StringRecord};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::{
+    format_amount, Client, ClientId, Engine, EngineError, EngineStats, ParseError, ProcessOutcome,
+    Transaction, DECIMAL_PLACES,
+};
+
+/// A raw csv row, matched against the header by column name
+///
+/// Fields are kept as strings so that validation is left to `parse_record`, missing columns are
+/// reported the same way as empty fields.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Row {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    client: Option<String>,
+    tx: Option<String>,
+    amount: Option<String>,
+    timestamp: Option<String>,
+}
+
+/// Access to the fields of a csv record, as either a `StringRecord` or a `ByteRecord`
+pub(crate) trait Fields {
+    /// Get a field as a str, or the lossily decoded field if it is not valid UTF-8
+    fn field(&self, index: usize) -> Option<Result<&str, String>>;
+
+    /// Number of fields in the record
+    fn field_count(&self) -> usize;
+
+    /// Reconstruct the original line of the record for error reporting
+    fn line(&self) -> String;
+}
+
+impl Fields for StringRecord {
+    fn field(&self, index: usize) -> Option<Result<&str, String>> {
+        self.get(index).map(Ok)
+    }
+
+    fn field_count(&self) -> usize {
+        self.len()
+    }
+
+    fn line(&self) -> String {
+        self.iter().collect::<Vec<_>>().join(",")
+    }
+}
+
+impl Fields for ByteRecord {
+    fn field(&self, index: usize) -> Option<Result<&str, String>> {
+        self.get(index).map(|field| {
+            std::str::from_utf8(field).map_err(|_| String::from_utf8_lossy(field).into_owned())
+        })
+    }
+
+    fn field_count(&self) -> usize {
+        self.len()
+    }
+
+    fn line(&self) -> String {
+        self.iter()
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(feature = "async")]
+impl Fields for csv_async::StringRecord {
+    fn field(&self, index: usize) -> Option<Result<&str, String>> {
+        self.get(index).map(Ok)
+    }
+
+    fn field_count(&self) -> usize {
+        self.len()
+    }
+
+    fn line(&self) -> String {
+        self.iter().collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Check for an amount with thousands separators that was split into several fields
+///
+/// An unquoted amount like `1,000.00` in comma separated input is read as the fields `1` and
+/// `000.00`, which would silently deposit or withdraw 1. If a record has more fields than its
+/// header, or than the `type, client, tx, amount` columns without a header, the amount is one to
+/// three digits and the fields after it are groups of three digits, of which only the last has a
+/// decimal point, the amount is reported as invalid. Other extra fields are ignored.
+pub(crate) fn split_amount<R: Fields>(record: &R, headers: Option<&R>) -> Option<ParseError> {
+    let (amount, columns) = match headers {
+        Some(headers) => {
+            let amount = (0..headers.field_count())
+                .position(|index| matches!(headers.field(index), Some(Ok("amount"))))?;
+            (amount, headers.field_count())
+        }
+        None => (3, COLUMNS.len()),
+    };
+    let extra = record.field_count().checked_sub(columns)?;
+    let is_digits = |value: &str| value.bytes().all(|b| b.is_ascii_digit());
+    let mut value = match record.field(amount) {
+        Some(Ok(value)) if (1..=3).contains(&value.len()) && is_digits(value) => value.to_string(),
+        _ => return None,
+    };
+    let mut groups = 0;
+    while groups < extra {
+        let Some(Ok(group)) = record.field(amount + 1 + groups) else {
+            break;
+        };
+        let (digits, decimals) = group.split_once('.').unwrap_or((group, ""));
+        if digits.len() != 3 || !is_digits(digits) || !is_digits(decimals) {
+            break;
+        }
+        value = format!("{},{}", value, group);
+        groups += 1;
+        if group.contains('.') {
+            break;
+        }
+    }
+    (groups > 0).then(|| ParseError::InvalidField {
+        line: record.line(),
+        field: "amount",
+        value,
+    })
+}
+
+/// Read the rows of a csv reader
+///
+/// Records whose amount was split by unquoted thousands separators are returned as parse errors,
+/// other records that can not be read as csv errors.
+pub(crate) fn rows<R: Read>(
+    csv_reader: &mut csv::Reader<R>,
+) -> Result<impl Iterator<Item = Result<Row, EngineError>> + '_, EngineError> {
+    let headers = match csv_reader.has_headers() {
+        true => Some(csv_reader.headers()?.clone()),
+        false => None,
+    };
+    Ok(csv_reader.records().map(move |record| {
+        let record = record?;
+        match split_amount(&record, headers.as_ref()) {
+            Some(err) => Err(err.into()),
+            None => Ok(record.deserialize(headers.as_ref())?),
+        }
+    }))
+}
+
+impl Row {
+    /// Check if the row has no values, such as a blank or whitespace-only line
+    pub(crate) fn is_blank(&self) -> bool {
+        [&self.kind, &self.client, &self.tx, &self.amount]
+            .into_iter()
+            .all(|field| field.as_deref().is_none_or(|value| value.trim().is_empty()))
+    }
+
+    /// Take the timestamp out of the row, parsing it if present
+    fn take_timestamp(&mut self) -> Result<Option<DateTime<FixedOffset>>, String> {
+        let Some(value) = self.timestamp.take().filter(|value| !value.is_empty()) else {
+            return Ok(None);
+        };
+        DateTime::parse_from_rfc3339(&value)
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f")
+                    .map(|time| time.and_utc().fixed_offset())
+            })
+            .map(Some)
+            .map_err(|_| value)
+    }
+
+    /// Convert into a record with the columns in `type, client, tx, amount` order
+    pub(crate) fn into_record(self) -> StringRecord {
+        [self.kind, self.client, self.tx, self.amount]
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect()
+    }
+}
+
+/// Records buffered to be applied in the order of their timestamp, see
+/// `EngineBuilder::order_by_time`
+#[derive(Default)]
+pub(crate) struct TimeOrder(Vec<(Option<DateTime<FixedOffset>>, StringRecord)>);
+
+impl TimeOrder {
+    /// Buffer a row, skipping it and returning false if its timestamp is invalid
+    pub(crate) fn push(
+        &mut self,
+        engine: &Engine,
+        mut row: Row,
+        stats: &mut ProcessStats,
+    ) -> Result<bool, EngineError> {
+        match row.take_timestamp() {
+            Ok(timestamp) => {
+                self.0.push((timestamp, row.into_record()));
+                Ok(true)
+            }
+            Err(value) => {
+                let record = row.into_record();
+                let err = ParseError::InvalidField {
+                    line: record.line(),
+                    field: "timestamp",
+                    value,
+                };
+                engine.skip_record(err.into(), stats)?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// The buffered records sorted by timestamp, records without one first
+    pub(crate) fn into_sorted(mut self) -> impl Iterator<Item = StringRecord> {
+        self.0.sort_by_key(|(timestamp, _)| *timestamp);
+        self.0.into_iter().map(|(_, record)| record)
+    }
+}
+
+/// Options controlling how the client list is written
+#[derive(Clone, Copy, Debug)]
+pub struct WriteOptions {
+    /// Write clients in ascending id order, enabled by default so output is reproducible
+    ///
+    /// Sorting takes O(n log n) time in the number of clients. Unsorted output skips collecting
+    /// and sorting the clients, which is faster for large datasets, but the order is unspecified.
+    pub sorted: bool,
+    /// Omit unlocked clients whose available, held and total funds are all zero
+    pub skip_zero: bool,
+    /// Write the `locked` column, enabled by default
+    ///
+    /// Without it the output has the four columns `client, available, held, total`.
+    pub locked_column: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            sorted: true,
+            skip_zero: false,
+            locked_column: true,
+        }
+    }
+}
+
+/// Number of records processed from csv input
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProcessStats {
+    /// Records that were parsed and applied
+    pub applied: usize,
+    /// Records that could not be parsed, were rejected or were ignored, such as a dispute of an
+    /// unknown transaction
+    pub skipped: usize,
+    /// Records skipped after the csv reader failed on them, see `EngineBuilder::skip_csv_errors`
+    pub errors: usize,
+}
+
+impl ProcessStats {
+    /// Check whether the input had no data rows, such as an empty or header-only file
+    ///
+    /// Blank lines are not counted as data rows.
+    pub fn is_empty(&self) -> bool {
+        self.applied == 0 && self.skipped == 0 && self.errors == 0
+    }
+}
+
+/// Issues found by `Engine::validate_reader`
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// Number of records read
+    pub records: usize,
+    /// Records that would be skipped, with their 1-based record number
+    pub issues: Vec<(usize, EngineError)>,
+}
+
+impl ValidationReport {
+    /// Check whether every record could be applied
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Columns the csv header must contain, in any order
+const COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// Check that a csv header contains all `COLUMNS`
+///
+/// An empty header, as read from empty input, is accepted since there are no rows to process.
+pub(crate) fn check_columns<'h>(
+    header: impl IntoIterator<Item = &'h str>,
+) -> Result<(), EngineError> {
+    let header: Vec<&str> = header.into_iter().collect();
+    if header.iter().all(|column| column.is_empty()) {
+        return Ok(());
+    }
+    let missing: Vec<&'static str> = COLUMNS
+        .into_iter()
+        .filter(|column| !header.contains(column))
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(EngineError::MissingColumns(missing))
+    }
+}
+
+/// Remove comma thousands separators from an amount like `1,000.00`
+///
+/// Returns `None` if the integer digits are not grouped by three.
+fn strip_thousands_separators(value: &str) -> Option<String> {
+    let unsigned = value.trim_start_matches(['-', '+']);
+    let integer = unsigned.split('.').next().unwrap_or_default();
+    let mut groups = integer.split(',');
+    let first = groups.next().unwrap_or_default();
+    let digits = |group: &str| group.bytes().all(|b| b.is_ascii_digit());
+    if !(1..=3).contains(&first.len()) || !digits(first) {
+        return None;
+    }
+    if !groups.all(|group| group.len() == 3 && digits(group)) {
+        return None;
+    }
+    Some(value.replace(',', ""))
+}
+
+/// Options for parsing records into transactions, set on the engine by the builder
+#[derive(Clone, Copy, Debug)]
+struct ParseOptions {
+    scale: u32,
+    thousands_separators: bool,
+    strict: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            scale: DECIMAL_PLACES,
+            thousands_separators: false,
+            strict: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Parse the fields of a record in `type, client, tx, amount` order into a Transaction
+    fn parse_fields<R: Fields>(&self, record: &R) -> Result<Transaction, ParseError> {
+        let kind = Self::field(record, 0, "type")?;
+        let ids = || -> Result<(ClientId, u32), ParseError> {
+            let client_id = Self::parse_field(record, 1, "client")?;
+            let tx = Self::parse_field(record, 2, "tx")?;
+            Ok((client_id, tx))
+        };
+        let reference = || -> Result<(ClientId, u32), ParseError> {
+            let ids = ids()?;
+            self.check_no_amount(record)?;
+            Ok(ids)
+        };
+        if kind.eq_ignore_ascii_case("deposit") {
+            let (client_id, tx) = ids()?;
+            let amount = self.parse_amount(record, 3)?;
+            Ok(Transaction::Deposit(client_id, tx, amount))
+        } else if kind.eq_ignore_ascii_case("withdrawal") {
+            let (client_id, tx) = ids()?;
+            let amount = self.parse_amount(record, 3)?;
+            Ok(Transaction::Withdrawal(client_id, tx, amount))
+        } else if kind.eq_ignore_ascii_case("dispute") {
+            let (client_id, tx) = reference()?;
+            Ok(Transaction::Dispute(client_id, tx))
+        } else if kind.eq_ignore_ascii_case("resolve") {
+            let (client_id, tx) = reference()?;
+            Ok(Transaction::Resolve(client_id, tx))
+        } else if kind.eq_ignore_ascii_case("chargeback") {
+            let (client_id, tx) = reference()?;
+            Ok(Transaction::Chargeback(client_id, tx))
+        } else if kind.eq_ignore_ascii_case("settle") {
+            let (client_id, tx) = reference()?;
+            Ok(Transaction::Settle(client_id, tx))
+        } else if kind.eq_ignore_ascii_case("freeze") {
+            Ok(Transaction::Freeze(Self::parse_field(record, 1, "client")?))
+        } else if kind.eq_ignore_ascii_case("unfreeze") {
+            Ok(Transaction::Unfreeze(Self::parse_field(
+                record, 1, "client",
+            )?))
+        } else {
+            Err(ParseError::UnknownType {
+                line: record.line(),
+                kind: kind.to_string(),
+            })
+        }
+    }
+
+    /// Get a trimmed, non-empty field from a record
+    fn field<'r, R: Fields>(
+        record: &'r R,
+        index: usize,
+        name: &'static str,
+    ) -> Result<&'r str, ParseError> {
+        match record.field(index) {
+            Some(Ok(value)) if !value.trim().is_empty() => Ok(value.trim()),
+            Some(Err(value)) => Err(ParseError::InvalidField {
+                line: record.line(),
+                field: name,
+                value,
+            }),
+            _ => Err(ParseError::MissingField {
+                line: record.line(),
+                field: name,
+            }),
+        }
+    }
+
+    /// In strict mode, check that a record referring to an earlier transaction has no amount
+    ///
+    /// Such records carry no amount, outside of strict mode the amount field is ignored.
+    fn check_no_amount<R: Fields>(&self, record: &R) -> Result<(), ParseError> {
+        match record.field(3) {
+            Some(Ok(value)) if self.strict && !value.trim().is_empty() => {
+                Err(ParseError::InvalidField {
+                    line: record.line(),
+                    field: "amount",
+                    value: value.trim().to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse an id field from a record into the integer type `T`
+    ///
+    /// Values too large for `T` are reported as out of range, other values as invalid.
+    fn parse_field<T: FromStr<Err = ParseIntError>, R: Fields>(
+        record: &R,
+        index: usize,
+        name: &'static str,
+    ) -> Result<T, ParseError> {
+        let value = Self::field(record, index, name)?;
+        value
+            .parse()
+            .map_err(|err: ParseIntError| match err.kind() {
+                IntErrorKind::PosOverflow => ParseError::OutOfRange {
+                    line: record.line(),
+                    field: name,
+                    value: value.to_string(),
+                    ty: std::any::type_name::<T>(),
+                },
+                _ => ParseError::InvalidField {
+                    line: record.line(),
+                    field: name,
+                    value: value.to_string(),
+                },
+            })
+    }
+
+    /// Parse an amount field
+    ///
+    /// Amounts must be non-negative and have at most as many decimals as the configured scale,
+    /// `DECIMAL_PLACES` by default. Non-finite values such as `NaN` can not be represented as a
+    /// `Decimal` and are rejected as invalid.
+    ///
+    /// If enabled, comma thousands separators such as in `1,000.00` are removed before parsing.
+    /// Otherwise, or if the digits are not grouped by three, such a value is rejected as invalid.
+    fn parse_amount<R: Fields>(&self, record: &R, index: usize) -> Result<Decimal, ParseError> {
+        let value = Self::field(record, index, "amount")?;
+        let ungrouped = self
+            .thousands_separators
+            .then(|| strip_thousands_separators(value))
+            .flatten();
+        let amount: Decimal = ungrouped.as_deref().unwrap_or(value).parse().map_err(|_| {
+            ParseError::InvalidField {
+                line: record.line(),
+                field: "amount",
+                value: value.to_string(),
+            }
+        })?;
+        if amount.is_sign_negative() && !amount.is_zero() {
+            return Err(ParseError::NegativeAmount {
+                line: record.line(),
+                value: value.to_string(),
+            });
+        }
+        if amount.normalize().scale() > self.scale {
+            return Err(ParseError::TooPrecise {
+                line: record.line(),
+                value: value.to_string(),
+                scale: self.scale,
+            });
+        }
+        Ok(amount)
+    }
+}
+
+/// Parse a StringRecord with the default options into a Transaction
+///
+/// Amounts may have at most `DECIMAL_PLACES` decimals and thousands separators are rejected, as
+/// for an engine created with `Engine::new`.
+pub fn parse_record(record: &StringRecord) -> Result<Transaction, ParseError> {
+    ParseOptions::default().parse_fields(record)
+}
+
+impl Client {
+    /// The client's fields formatted as an output csv record
+    fn record(&self, precision: u32) -> [String; 5] {
+        [
+            self.id.to_string(),
+            format_amount(self.available, precision),
+            format_amount(self.held, precision),
+            format_amount(self.total, precision),
+            self.locked.to_string(),
+        ]
+    }
+}
+
+impl Engine {
+    /// Read csv records from a file
+    ///
+    /// With the `gzip` feature, files with a `.gz` extension are decompressed while reading.
+    pub fn read_file(&mut self, file: &Path) -> Result<ProcessStats, EngineError> {
+        let f = BufReader::new(File::open(file)?);
+        #[cfg(feature = "gzip")]
+        if file.extension().is_some_and(|ext| ext == "gz") {
+            return self.from_reader(flate2::read::GzDecoder::new(f));
+        }
+        self.from_reader(f)
+    }
+
+    /// Read all `*.csv` files in a directory, in file name order
+    ///
+    /// The files are applied to the same engine state, so later files can dispute transactions
+    /// from earlier ones. With the `gzip` feature, `*.csv.gz` files are read as well.
+    pub fn read_dir(&mut self, dir: &Path) -> Result<ProcessStats, EngineError> {
+        let is_csv = |path: &Path| path.extension().is_some_and(|ext| ext == "csv");
+        let is_csv_gz = |path: &Path| {
+            cfg!(feature = "gzip")
+                && path.extension().is_some_and(|ext| ext == "gz")
+                && path.file_stem().is_some_and(|stem| is_csv(Path::new(stem)))
+        };
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && (is_csv(&path) || is_csv_gz(&path)) {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        let mut stats = ProcessStats::default();
+        for file in files {
+            let file_stats = self.read_file(&file)?;
+            stats.applied += file_stats.applied;
+            stats.skipped += file_stats.skipped;
+            stats.errors += file_stats.errors;
+        }
+        Ok(stats)
+    }
+
+    /// Read csv records from a str
+    pub fn from_str(&mut self, csv: &str) -> Result<ProcessStats, EngineError> {
+        self.from_reader(csv.as_bytes())
+    }
+
+    /// Read csv records from any reader
+    ///
+    /// Columns are matched by their header name, so they may appear in any order. Extra columns
+    /// are ignored, while a header missing any of the `type`, `client`, `tx` and `amount` columns
+    /// is an error before any record is processed. Empty fields are reported as parse errors.
+    ///
+    /// Blank lines are skipped silently, other records that can not be parsed or applied are
+    /// logged and skipped. In strict mode records that can not be parsed abort processing instead.
+    /// Records the csv reader fails on abort processing unless skipped with
+    /// `EngineBuilder::skip_csv_errors`, while io errors always abort it. A leading UTF-8 byte
+    /// order mark, as written by Excel, is stripped by the csv reader.
+    ///
+    /// If enabled with `EngineBuilder::order_by_time`, all records are read first and then applied
+    /// in the order of their timestamp.
+    pub fn from_reader<R: Read>(&mut self, reader: R) -> Result<ProcessStats, EngineError> {
+        self.read_with(reader, |_, _| {})
+    }
+
+    /// Read csv records from any reader, calling `f` with the engine's stats every `every` records
+    ///
+    /// Records are handled as by `from_reader`, both applied and skipped records are counted. The
+    /// callback is not called for the remaining records after the last multiple of `every`, nor
+    /// at all if `every` is zero.
+    pub fn process_with_flush<R: Read, F: FnMut(&EngineStats)>(
+        &mut self,
+        reader: R,
+        every: usize,
+        mut f: F,
+    ) -> Result<ProcessStats, EngineError> {
+        self.read_with(reader, |engine, stats| {
+            let records = stats.applied + stats.skipped;
+            if every > 0 && records % every == 0 {
+                f(&engine.stats());
+            }
+        })
+    }
+
+    /// Read csv records from any reader, calling `after` each time a record has been counted
+    fn read_with<R: Read, F: FnMut(&Self, &ProcessStats)>(
+        &mut self,
+        reader: R,
+        mut after: F,
+    ) -> Result<ProcessStats, EngineError> {
+        let mut stats = ProcessStats::default();
+        let mut csv_reader = self.csv_reader(reader)?;
+        let mut timed = TimeOrder::default();
+        for row in rows(&mut csv_reader)? {
+            let row = match row {
+                Ok(row) => row,
+                Err(EngineError::Csv(err)) => {
+                    self.skip_csv_error(err, &mut stats)?;
+                    continue;
+                }
+                Err(err) => {
+                    self.skip_record(err, &mut stats)?;
+                    after(self, &stats);
+                    continue;
+                }
+            };
+            if row.is_blank() {
+                continue;
+            }
+            if !self.order_by_time {
+                self.read_record(&row.into_record(), &mut stats)?;
+                after(self, &stats);
+                continue;
+            }
+            if !timed.push(self, row, &mut stats)? {
+                after(self, &stats);
+            }
+        }
+        for record in timed.into_sorted() {
+            self.read_record(&record, &mut stats)?;
+            after(self, &stats);
+        }
+        Ok(stats)
+    }
+
+    /// Parse and apply records that have already been read, such as from a split input
+    ///
+    /// Fields are expected in `type, client, tx, amount` order. Records that can not be parsed or
+    /// applied are logged and skipped, also in strict mode, and the transaction limit does not
+    /// apply.
+    pub fn process_records<I: IntoIterator<Item = StringRecord>>(
+        &mut self,
+        records: I,
+    ) -> ProcessStats {
+        let mut stats = ProcessStats::default();
+        for record in records {
+            match self.process_record(&record) {
+                Ok(ProcessOutcome::Applied) => stats.applied += 1,
+                Ok(_) => stats.skipped += 1,
+                Err(err) => {
+                    log::log!(err.level(), "Skipping record: {}", err);
+                    stats.skipped += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// Apply a record read from csv input, counting it in `stats`
+    ///
+    /// Returns an error only if the record should abort processing, either because it is invalid
+    /// in strict mode or because the maximum number of transactions has been read.
+    pub(crate) fn read_record(
+        &mut self,
+        record: &StringRecord,
+        stats: &mut ProcessStats,
+    ) -> Result<(), EngineError> {
+        if let Some(limit) = self.max_transactions {
+            if stats.applied + stats.skipped >= limit {
+                return Err(EngineError::LimitExceeded { limit });
+            }
+        }
+        match self.process_record(record) {
+            Err(err) => self.skip_record(err, stats),
+            Ok(outcome) => {
+                match outcome {
+                    ProcessOutcome::Applied => stats.applied += 1,
+                    _ => stats.skipped += 1,
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Skip a record that could not be processed, or return the error in strict mode
+    pub(crate) fn skip_record(
+        &self,
+        err: EngineError,
+        stats: &mut ProcessStats,
+    ) -> Result<(), EngineError> {
+        if self.strict && matches!(err, EngineError::Parse(_)) {
+            return Err(err);
+        }
+        log::log!(err.level(), "Skipping record: {}", err);
+        stats.skipped += 1;
+        Ok(())
+    }
+
+    /// Skip a record the csv reader failed on if enabled, or return the error
+    pub(crate) fn skip_csv_error(
+        &self,
+        err: csv::Error,
+        stats: &mut ProcessStats,
+    ) -> Result<(), EngineError> {
+        if !self.skip_csv_errors || err.is_io_error() {
+            return Err(err.into());
+        }
+        log::warn!("Skipping malformed record: {}", err);
+        stats.errors += 1;
+        Ok(())
+    }
+
+    /// Check every record from a reader without changing the engine's state
+    ///
+    /// Records are applied to a copy of the engine so that issues depending on earlier records,
+    /// such as withdrawals exceeding the available funds, are reported as well. Io and csv errors
+    /// abort validation.
+    pub fn validate_reader<R: Read>(&self, reader: R) -> Result<ValidationReport, EngineError> {
+        let mut scratch = self.clone();
+        let mut report = ValidationReport::default();
+        let mut csv_reader = self.csv_reader(reader)?;
+        for row in rows(&mut csv_reader)? {
+            let row = match row {
+                Ok(row) => row,
+                Err(err @ EngineError::Parse(_)) => {
+                    report.records += 1;
+                    report.issues.push((report.records, err));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if row.is_blank() {
+                continue;
+            }
+            let record = row.into_record();
+            report.records += 1;
+
+            if let Err(err) = scratch.process_record(&record) {
+                report.issues.push((report.records, err));
+            }
+        }
+        Ok(report)
+    }
+
+    /// List the ids of deposits and withdrawals that appear more than once in a reader
+    ///
+    /// The input is read in the default format, a comma separated file with a header. The ids are
+    /// returned in ascending order, each only once. Records that can not be parsed are ignored, io
+    /// and csv errors abort the scan.
+    pub fn find_duplicate_txids<R: Read>(reader: R) -> Result<Vec<u32>, EngineError> {
+        let engine = Engine::new();
+        let mut seen = HashSet::new();
+        let mut duplicates = BTreeSet::new();
+        let mut csv_reader = engine.csv_reader(reader)?;
+        for row in rows(&mut csv_reader)? {
+            let row = match row {
+                Ok(row) => row,
+                Err(EngineError::Parse(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            if row.is_blank() {
+                continue;
+            }
+            if let Ok(Transaction::Deposit(_, tx, _) | Transaction::Withdrawal(_, tx, _)) =
+                engine.parse_record(&row.into_record())
+            {
+                if !seen.insert(tx) {
+                    duplicates.insert(tx);
+                }
+            }
+        }
+        Ok(duplicates.into_iter().collect())
+    }
+
+    /// Create a csv reader for the engine's input format
+    ///
+    /// If the input has a header, its fields are renamed as configured and it is checked to
+    /// contain all columns before any row is read.
+    pub(crate) fn csv_reader<R: Read>(&self, reader: R) -> Result<csv::Reader<R>, EngineError> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        if self.has_headers {
+            let headers: StringRecord = self.map_headers(csv_reader.headers()?);
+            check_columns(&headers)?;
+            csv_reader.set_headers(headers);
+        }
+        Ok(csv_reader)
+    }
+
+    /// Rename the fields of a csv header to the column names configured with
+    /// `EngineBuilder::column_name`
+    pub(crate) fn map_headers<'h, H: FromIterator<&'h str>>(
+        &'h self,
+        headers: impl IntoIterator<Item = &'h str>,
+    ) -> H {
+        headers
+            .into_iter()
+            .map(|field| self.column_names.get(field).map_or(field, String::as_str))
+            .collect()
+    }
+
+    /// Parse a csv record and apply it
+    fn process_record(&mut self, record: &StringRecord) -> Result<ProcessOutcome, EngineError> {
+        let record = self.parse_record(record)?;
+        self.apply(record)
+    }
+
+    /// The engine's options for parsing records
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            scale: self.scale,
+            thousands_separators: self.thousands_separators,
+            strict: self.strict,
+        }
+    }
+
+    /// Parse a StringRecord into a Transaction
+    ///
+    /// Same as the free function `parse_record`, but using the engine's scale and thousands
+    /// separator settings.
+    pub fn parse_record(&self, record: &StringRecord) -> Result<Transaction, ParseError> {
+        self.parse_options().parse_fields(record)
+    }
+
+    /// Parse a ByteRecord into a Transaction
+    ///
+    /// Fields are parsed directly from the record's bytes without allocating, for reading large
+    /// inputs with `csv::Reader::read_byte_record`. The result is the same as for `parse_record`,
+    /// fields that are not valid UTF-8 are rejected as invalid.
+    pub fn parse_byte_record(&self, record: &ByteRecord) -> Result<Transaction, ParseError> {
+        self.parse_options().parse_fields(record)
+    }
+
+    /// Write the client list as csv to a writer, in ascending id order
+    pub fn write_clients<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_clients_with(w, WriteOptions::default())
+    }
+
+    /// Write the client list as csv to a writer using the given options
+    pub fn write_clients_with<W: Write>(&self, w: &mut W, options: WriteOptions) -> io::Result<()> {
+        let columns = if options.locked_column { 5 } else { 4 };
+        let mut writer = csv::Writer::from_writer(w);
+        writer.write_record(&["client", "available", "held", "total", "locked"][..columns])?;
+        let include = |client: &Client| !(options.skip_zero && client.is_empty());
+        if options.sorted {
+            for client in self.clients().filter(include) {
+                writer.write_record(&client.record(self.precision)[..columns])?;
+            }
+        } else {
+            for client in self.clients.values().filter(|client| include(client)) {
+                writer.write_record(&client.record(self.precision)[..columns])?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use csv::{ByteRecord, StringRecord};
+    use rust_decimal_macros::dec;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn read_line() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 1.0";
+
+        let res = engine.from_str(csv);
+        assert!(res.is_ok())
+    }
+
+    #[test]
+    fn process_stats() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, 1, x, 1.0
+withdrawal, 1, 2, 1.0
+withdrawal, 1, 3, 5.0
+deposit, 2, 4, 1.0";
+
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(
+            stats,
+            ProcessStats {
+                applied: 3,
+                skipped: 2,
+                errors: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn read_dir() {
+        let dir = std::env::temp_dir().join(format!("ta-read-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("2022-01-02.csv"),
+            "type, client, tx, amount\ndispute, 1, 1,\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("2022-01-01.csv"),
+            "type, client, tx, amount\ndeposit, 1, 1, 2.0\ndeposit, 1, 2, 1.0\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a csv file").unwrap();
+
+        let mut engine = Engine::new();
+        let stats = engine.read_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            stats.unwrap(),
+            ProcessStats {
+                applied: 3,
+                skipped: 0,
+                errors: 0,
+            }
+        );
+        assert!(engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().available == dec!(1.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_dir_gzip() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("ta-read-dir-gzip-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(b"type, client, tx, amount\ndeposit, 1, 1, 2.0\n")
+            .unwrap();
+        fs::write(dir.join("2022-01-01.csv.gz"), encoder.finish().unwrap()).unwrap();
+        fs::write(
+            dir.join("2022-01-02.csv"),
+            "type, client, tx, amount\ndispute, 1, 1,\n",
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt.gz"), "not a csv file").unwrap();
+
+        let mut engine = Engine::new();
+        let stats = engine.read_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(stats.unwrap().applied, 2);
+        assert!(engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_file_gzip() {
+        use std::io::Write;
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, 2, 2, 1.5
+withdrawal, 1, 3, 0.5
+dispute, 2, 2,";
+        let dir = std::env::temp_dir().join(format!("ta-read-gzip-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(csv.as_bytes()).unwrap();
+        fs::write(dir.join("transactions.csv.gz"), encoder.finish().unwrap()).unwrap();
+
+        let mut engine = Engine::new();
+        let stats = engine.read_file(&dir.join("transactions.csv.gz"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut expected = Engine::new();
+        assert_eq!(stats.unwrap(), expected.from_str(csv).unwrap());
+        for id in [1, 2] {
+            assert_eq!(
+                engine.client(id).unwrap().to_string(),
+                expected.client(id).unwrap().to_string()
+            );
+        }
+        assert!(engine.client(2).unwrap().held == dec!(1.5));
+    }
+
+    #[test]
+    fn read_file_empty() {
+        let dir = std::env::temp_dir().join(format!("ta-read-empty-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("header.csv"), "type, client, tx, amount\n\n").unwrap();
+        fs::write(dir.join("empty.csv"), "").unwrap();
+        fs::write(
+            dir.join("invalid.csv"),
+            "type, client, tx, amount\nfoo, 1, 1,\n",
+        )
+        .unwrap();
+
+        let mut engine = Engine::new();
+        let header = engine.read_file(&dir.join("header.csv"));
+        let empty = engine.read_file(&dir.join("empty.csv"));
+        let invalid = engine.read_file(&dir.join("invalid.csv"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(header.unwrap().is_empty());
+        assert!(empty.unwrap().is_empty());
+        assert!(!invalid.unwrap().is_empty());
+        assert_eq!(engine.clients().count(), 0);
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        let mut engine = Engine::new();
+        let csv = "\u{feff}type, client, tx, amount
+deposit, 1, 1, 2.0";
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.applied, 1);
+
+        let mut engine = Engine::builder().has_headers(false).build();
+        let csv = "\u{feff}deposit, 1, 1, 2.0";
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.applied, 1);
+    }
+
+    #[test]
+    fn process_with_flush() {
+        let mut engine = Engine::new();
+
+        let mut csv = String::from("type, client, tx, amount\n");
+        for tx in 1..=9 {
+            csv.push_str(&format!("deposit, 1, {}, 1.0\n", tx));
+        }
+        csv.push_str("deposit, 1, x, 1.0\n");
+
+        let mut flushed = Vec::new();
+        let stats = engine
+            .process_with_flush(csv.as_bytes(), 3, |stats| flushed.push(stats.total))
+            .unwrap();
+        assert_eq!(stats.applied + stats.skipped, 10);
+        assert_eq!(flushed, [dec!(3.0), dec!(6.0), dec!(9.0)]);
+    }
+
+    #[test]
+    fn skip_csv_errors() {
+        let csv: &[u8] = b"type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, 1, 2, \xff\xfe
+deposit, 1, 3, 1.0";
+
+        let mut engine = Engine::new();
+        assert!(matches!(engine.from_reader(csv), Err(EngineError::Csv(_))));
+
+        let mut engine = Engine::builder().skip_csv_errors(true).build();
+        let stats = engine.from_reader(csv).unwrap();
+        assert_eq!(
+            stats,
+            ProcessStats {
+                applied: 2,
+                skipped: 0,
+                errors: 1,
+            }
+        );
+        assert!(engine.client(1).unwrap().total == dec!(3.0));
+    }
+
+    #[test]
+    fn clients_equal() {
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, 2, 2, 1.0
+withdrawal, 1, 3, 0.5
+deposit, 2, 4, 3.0
+dispute, 2, 4,";
+        let reordered = "type, client, tx, amount
+deposit, 2, 2, 1.0
+deposit, 2, 4, 3.00
+deposit, 1, 1, 2.0
+dispute, 2, 4,
+withdrawal, 1, 3, 0.5";
+
+        let mut engine = Engine::new();
+        engine.from_str(csv).unwrap();
+        let mut other = Engine::new();
+        other.from_str(reordered).unwrap();
+        assert!(engine.clients_equal(&other));
+
+        other.handle_record(Transaction::Resolve(2, 4));
+        assert!(!engine.clients_equal(&other));
+        assert!(!engine.clients_equal(&Engine::new()));
+
+        let mut engine = Engine::builder().fixed_client_scale(true).build();
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(1.0)));
+        let mut other = Engine::builder().fixed_client_scale(true).build();
+        other.handle_record(Transaction::Deposit(1, 1, dec!(1.00)));
+        assert!(!engine.clients_equal(&other));
+    }
+
+    #[test]
+    fn column_names() {
+        let csv = "action, account, txid, value
+deposit, 1, 1, 2.0
+withdrawal, 1, 2, 0.5";
+
+        let mut engine = Engine::new();
+        assert!(matches!(
+            engine.from_str(csv),
+            Err(EngineError::MissingColumns(_))
+        ));
+
+        let mut engine = Engine::builder()
+            .column_name("type", "action")
+            .column_name("client", "account")
+            .column_name("tx", "txid")
+            .column_name("amount", "value")
+            .build();
+        assert_eq!(engine.from_str(csv).unwrap().applied, 2);
+        assert!(engine.client(1).unwrap().total == dec!(1.5));
+    }
+
+    #[test]
+    fn blank_lines() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount\n\
+                   deposit, 1, 1, 1.0\n\
+                   \n\
+                   deposit, 1, 2, 2.0\n   \n\t\n\
+                   withdrawal, 1, 3, 0.5\n\
+                   , , , \n";
+
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(
+            stats,
+            ProcessStats {
+                applied: 3,
+                skipped: 0,
+                errors: 0,
+            }
+        );
+        assert!(engine.client(1).unwrap().total == dec!(2.5));
+    }
+
+    #[test]
+    fn missing_columns() {
+        let mut engine = Engine::new();
+
+        let csv = "type, tx, client
+deposit, 1, 1";
+
+        let res = engine.from_str(csv);
+        assert!(matches!(
+            res,
+            Err(EngineError::MissingColumns(ref columns)) if columns == &["amount"]
+        ));
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "csv header is missing the columns: amount"
+        );
+        assert!(engine.client(1).is_none());
+
+        let res = engine.from_str("kind, client, id, amount\n");
+        assert!(matches!(
+            res,
+            Err(EngineError::MissingColumns(ref columns)) if columns == &["type", "tx"]
+        ));
+    }
+
+    #[test]
+    fn order_by_time() {
+        let csv = "type, client, tx, amount, timestamp
+withdrawal, 1, 3, 2.0, 2024-01-01T12:00:00Z
+deposit, 1, 1, 5.0, 2024-01-01T10:00:00Z
+dispute, 1, 1, , 2024-01-01T13:00:00+02:00
+deposit, 1, 2, 1.0, 2024-01-01T11:30:00
+deposit, 1, 4, 1.0, yesterday";
+
+        let mut engine = Engine::builder()
+            .order_by_time(true)
+            .track_history(true)
+            .build();
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(
+            stats,
+            ProcessStats {
+                applied: 3,
+                skipped: 2,
+                errors: 0,
+            }
+        );
+        assert_eq!(engine.history(1), [dec!(5.0), dec!(0.0), dec!(1.0)]);
+        assert!(engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().total == dec!(6.0));
+
+        let mut engine = Engine::new();
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.applied, 4);
+        assert!(engine.client(1).unwrap().total == dec!(7.0));
+    }
+
+    #[test]
+    fn max_transactions() {
+        let mut engine = Engine::builder().max_transactions(2).build();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, 1.0
+deposit, 1, 3, 1.0
+deposit, 1, 4, 1.0
+deposit, 1, 5, 1.0";
+
+        let res = engine.from_str(csv);
+        assert!(matches!(res, Err(EngineError::LimitExceeded { limit: 2 })));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+        assert_eq!(engine.transaction_counts(1).unwrap().deposits, 2);
+    }
+
+    #[test]
+    fn read_from_reader() {
+        let mut engine = Engine::new();
+
+        let csv = b"type, client, tx, amount
+deposit, 1, 1, 1.5
+withdrawal, 1, 2, 0.5"
+            .to_vec();
+
+        let res = engine.from_reader(std::io::Cursor::new(csv));
+        assert!(res.is_ok());
+        assert!(engine.client(1).unwrap().available == dec!(1.0));
+        assert!(engine.client(1).unwrap().total == dec!(1.0));
+    }
+
+    #[test]
+    fn write_clients() {
+        let mut engine = Engine::new();
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(1.5)));
+
+        let mut out = Vec::new();
+        engine.write_clients(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n"
+        );
+    }
+
+    #[test]
+    fn write_clients_precision() {
+        let mut engine = Engine::new();
+        engine.set_precision(2);
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(1.235)));
+        engine.handle_record(Transaction::Deposit(2, 2, dec!(1.2349)));
+
+        let mut out = Vec::new();
+        engine.write_clients(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("client,available,held,total,locked\n"));
+        assert!(out.contains("1,1.24,0.00,1.24,false\n"));
+        assert!(out.contains("2,1.23,0.00,1.23,false\n"));
+    }
+
+    #[test]
+    fn read_file_errors() {
+        let mut engine = Engine::new();
+
+        let res = engine.read_file(Path::new("does/not/exist.csv"));
+        assert!(matches!(res, Err(EngineError::Io(_))));
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 1, 2, abc";
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let errors: Vec<EngineError> = reader
+            .records()
+            .filter_map(|record| engine.process_record(&record.unwrap()).err())
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            EngineError::Parse(ParseError::InvalidField {
+                field: "amount",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn read_columns_by_name() {
+        let mut engine = Engine::new();
+
+        let csv = "client, type, amount, tx
+1, deposit, 2.5, 1
+1, withdrawal, 1.0, 2
+1, dispute, , 1";
+
+        let res = engine.from_str(csv);
+        assert!(res.is_ok());
+        assert!(engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(-1.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.5));
+        assert!(engine.client(1).unwrap().total == dec!(1.5));
+    }
+
+    #[test]
+    fn builder_strict() {
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, x, 2, 1.0
+deposit, 1, 3, 1.0";
+
+        let mut engine = Engine::builder().strict(true).build();
+        let res = engine.from_str(csv);
+        assert!(matches!(
+            res,
+            Err(EngineError::Parse(ParseError::InvalidField {
+                field: "client",
+                ..
+            }))
+        ));
+        assert!(engine.client(1).unwrap().total == dec!(1.0));
+
+        let mut engine = Engine::builder().strict(false).build();
+        assert!(engine.from_str(csv).is_ok());
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn strict_dispute_amount() {
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 5.0
+dispute, 1, 1, 5.0";
+
+        let mut engine = Engine::builder().strict(true).build();
+        assert!(matches!(
+            engine.from_str(csv),
+            Err(EngineError::Parse(ParseError::InvalidField {
+                field: "amount",
+                ..
+            }))
+        ));
+        assert!(!engine.is_disputed(1));
+
+        let mut engine = Engine::new();
+        assert_eq!(engine.from_str(csv).unwrap().applied, 2);
+        assert!(engine.is_disputed(1));
+    }
+
+    #[test]
+    fn builder_options() {
+        let mut engine = Engine::builder().expected_clients(16).precision(2).build();
+        assert!(engine.clients.capacity() >= 16);
+
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(1.5)));
+        let mut out = Vec::new();
+        engine.write_clients(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n1,1.50,0.00,1.50,false\n"
+        );
+    }
+
+    #[test]
+    fn write_clients_sorted() {
+        let mut engine = Engine::new();
+        for id in [5, 3, 9, 1, 7] {
+            engine.handle_record(Transaction::Deposit(id, id, dec!(1.0)));
+        }
+
+        let mut out = Vec::new();
+        engine
+            .write_clients_with(
+                &mut out,
+                WriteOptions {
+                    sorted: true,
+                    ..WriteOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked
+1,1.0000,0.0000,1.0000,false
+3,1.0000,0.0000,1.0000,false
+5,1.0000,0.0000,1.0000,false
+7,1.0000,0.0000,1.0000,false
+9,1.0000,0.0000,1.0000,false
+"
+        );
+    }
+
+    #[test]
+    fn scale() {
+        let mut engine = Engine::builder().scale(8).build();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 0.12345678
+withdrawal, 1, 2, 0.00000001
+deposit, 1, 3, 0.123456789";
+
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.skipped, 1);
+
+        let mut out = Vec::new();
+        engine.write_clients(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n1,0.12345677,0.00000000,0.12345677,false\n"
+        );
+
+        let record = StringRecord::from(vec!["deposit", "1", "4", "0.123456789"]);
+        assert_eq!(
+            engine.parse_record(&record).unwrap_err().to_string(),
+            r#"amount "0.123456789" has more than 8 decimal places in record: "deposit,1,4,0.123456789""#
+        );
+    }
+
+    #[test]
+    fn write_clients_default_order() {
+        let mut engine = Engine::new();
+        for id in [3, 1, 2] {
+            engine.handle_record(Transaction::Deposit(id, id, dec!(1.0)));
+        }
+
+        let mut out = Vec::new();
+        engine.write_clients(&mut out).unwrap();
+        let ids: Vec<&str> = std::str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap())
+            .collect();
+        assert_eq!(ids, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn write_clients_skip_zero() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Withdrawal(1, 2, dec!(1.0)),
+            Transaction::Deposit(2, 3, dec!(2.0)),
+        ]);
+
+        let mut out = Vec::new();
+        engine
+            .write_clients_with(
+                &mut out,
+                WriteOptions {
+                    skip_zero: true,
+                    ..WriteOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked\n2,2.0000,0.0000,2.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn write_clients_without_locked_column() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Deposit(2, 2, dec!(2.0)),
+            Transaction::Dispute(2, 2),
+            Transaction::Chargeback(2, 2),
+        ]);
+
+        let mut out = Vec::new();
+        engine.write_clients(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked
+1,1.0000,0.0000,1.0000,false
+2,0.0000,0.0000,0.0000,true
+"
+        );
+
+        let mut out = Vec::new();
+        engine
+            .write_clients_with(
+                &mut out,
+                WriteOptions {
+                    locked_column: false,
+                    ..WriteOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total
+1,1.0000,0.0000,1.0000
+2,0.0000,0.0000,0.0000
+"
+        );
+    }
+
+    #[test]
+    fn validate_reader() {
+        let engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, 1, x, 1.0
+withdrawal, 1, 3, 5.0
+withdrawal, 1, 4, 1.0";
+
+        let report = engine.validate_reader(csv.as_bytes()).unwrap();
+        assert_eq!(report.records, 4);
+        assert!(!report.is_valid());
+        assert_eq!(report.issues.len(), 2);
+        assert!(matches!(
+            report.issues[0],
+            (
+                2,
+                EngineError::Parse(ParseError::InvalidField { field: "tx", .. })
+            )
+        ));
+        assert!(matches!(
+            report.issues[1],
+            (3, EngineError::InsufficientFunds { client: 1, tx: 3 })
+        ));
+        assert!(engine.client(1).is_none());
+    }
+
+    #[test]
+    fn read_ignored_records() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+dispute, 1, 99,
+resolve, 1, 1,";
+
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(
+            stats,
+            ProcessStats {
+                applied: 1,
+                skipped: 2,
+                errors: 0,
+            }
+        );
+
+        let records = csv
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').map(str::trim).collect::<StringRecord>());
+        let stats = Engine::new().process_records(records);
+        assert_eq!(stats.applied, 1);
+        assert_eq!(stats.skipped, 2);
+    }
+
+    #[test]
+    fn read_freeze() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+freeze, 1,,
+deposit, 1, 2, 1.0
+freeze, 2,,
+unfreeze, 2,,";
+
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.applied, 4);
+        assert_eq!(stats.skipped, 1);
+        assert!(engine.client(1).unwrap().locked);
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+        assert!(!engine.client(2).unwrap().locked);
+    }
+
+    #[test]
+    fn find_duplicate_txids() {
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, 1, 2, 1.0
+dispute, 1, 1,
+withdrawal, 2, 1, 1.0
+deposit, 1, 3, 1.0";
+
+        assert_eq!(
+            Engine::find_duplicate_txids(csv.as_bytes()).unwrap(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn read_delimiters() {
+        for (delimiter, csv) in [
+            (
+                b';',
+                "type;client;tx;amount\ndeposit;1;1;2.5\nwithdrawal;1;2;1.0\n",
+            ),
+            (
+                b'\t',
+                "type\tclient\ttx\tamount\ndeposit\t1\t1\t2.5\nwithdrawal\t1\t2\t1.0\n",
+            ),
+        ] {
+            let mut engine = Engine::builder().delimiter(delimiter).build();
+            assert!(engine.from_str(csv).is_ok());
+            assert!(engine.client(1).unwrap().available == dec!(1.5));
+            assert!(engine.client(1).unwrap().total == dec!(1.5));
+        }
+    }
+
+    #[test]
+    fn read_headerless() {
+        let csv = "deposit, 1, 1, 2.5
+withdrawal, 1, 2, 1.0
+dispute, 1, 1,";
+
+        let mut engine = Engine::builder().has_headers(false).build();
+        assert!(engine.from_str(csv).is_ok());
+        assert!(engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().available == dec!(-1.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.5));
+        assert!(engine.client(1).unwrap().total == dec!(1.5));
+    }
+
+    #[test]
+    fn parse_records() {
+        let engine = Engine::new();
+
+        let records = [
+            StringRecord::from(vec!["deposit", "1", "1", "1.0"]),
+            StringRecord::from(vec!["withdrawal", "1", "1", "1.0"]),
+            StringRecord::from(vec!["dispute", "1", "1", ""]),
+            StringRecord::from(vec!["resolve", "1", "1", ""]),
+            StringRecord::from(vec!["chargeback", "1", "1", ""]),
+            StringRecord::from(vec!["settle", "1", "1", ""]),
+            StringRecord::from(vec!["freeze", "1"]),
+            StringRecord::from(vec!["unfreeze", "1", "", ""]),
+        ];
+
+        let expected = [
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Withdrawal(1, 1, dec!(1.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Resolve(1, 1),
+            Transaction::Chargeback(1, 1),
+            Transaction::Settle(1, 1),
+            Transaction::Freeze(1),
+            Transaction::Unfreeze(1),
+        ];
+
+        records.into_iter().enumerate().for_each(|(i, record)| {
+            assert!(engine.parse_record(&record).unwrap() == expected[i]);
+        });
+    }
+
+    #[test]
+    fn process_records() {
+        let mut engine = Engine::new();
+        let records = vec![
+            StringRecord::from(vec!["deposit", "1", "1", "2.0"]),
+            StringRecord::from(vec!["deposit", "2", "2", "1.0"]),
+            StringRecord::from(vec!["withdrawal", "1", "3", "5.0"]),
+            StringRecord::from(vec!["dispute", "2", "2", ""]),
+            StringRecord::from(vec!["deposit", "x", "4", "1.0"]),
+        ];
+
+        let stats = engine.process_records(records);
+        assert_eq!(
+            stats,
+            ProcessStats {
+                applied: 3,
+                skipped: 2,
+                errors: 0,
+            }
+        );
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+        assert!(engine.client(2).unwrap().held == dec!(1.0));
+    }
+
+    #[test]
+    fn parse_record_without_engine() {
+        let record = StringRecord::from(vec!["withdrawal", "2", "3", "1.5"]);
+        assert_eq!(
+            parse_record(&record),
+            Ok(Transaction::Withdrawal(2, 3, dec!(1.5)))
+        );
+
+        let record = StringRecord::from(vec!["deposit", "1", "1", "1,000.5"]);
+        assert!(matches!(
+            parse_record(&record),
+            Err(ParseError::InvalidField {
+                field: "amount",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_byte_records() {
+        let engine = Engine::new();
+
+        let csv = "deposit,1,1,1.5
+Withdrawal, 2, 2, 0.25
+dispute,1,1,
+resolve,1,1
+chargeback,1,1,
+settle,1,1,
+deposit,1,3,-1.0
+deposit,x,4,1.0
+transfer,1,5,1.0";
+        let string_records = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(csv.as_bytes())
+            .into_records()
+            .map(Result::unwrap);
+        let byte_records = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(csv.as_bytes())
+            .into_byte_records()
+            .map(Result::unwrap);
+
+        for (string_record, byte_record) in string_records.zip(byte_records) {
+            assert_eq!(
+                engine.parse_byte_record(&byte_record),
+                engine.parse_record(&string_record)
+            );
+        }
+
+        let invalid = ByteRecord::from(vec![&b"deposit"[..], b"1", b"\xff", b"1.0"]);
+        assert!(matches!(
+            engine.parse_byte_record(&invalid),
+            Err(ParseError::InvalidField { field: "tx", .. })
+        ));
+    }
+
+    #[test]
+    fn parse_type_case_and_whitespace() {
+        let engine = Engine::new();
+
+        let records = [
+            StringRecord::from(vec!["Deposit", "1", "1", "1.0"]),
+            StringRecord::from(vec!["DEPOSIT", "1", "1", "1.0"]),
+            StringRecord::from(vec![" deposit", "1", "1", "1.0"]),
+            StringRecord::from(vec!["  WithDrawal  ", "1", "1", "1.0"]),
+        ];
+
+        let expected = [
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Withdrawal(1, 1, dec!(1.0)),
+        ];
+
+        records.into_iter().enumerate().for_each(|(i, record)| {
+            assert!(engine.parse_record(&record).unwrap() == expected[i]);
+        });
+    }
+
+    #[test]
+    fn log_unknown_type() {
+        test_logger::init();
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+transfer, 1, 4242, 1.0";
+
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.skipped, 1);
+        assert!(test_logger::logged(
+            r#"WARN Skipping record: parse error: unknown transaction type "transfer" in record: "transfer,1,4242,1.0""#
+        ));
+    }
+
+    #[test]
+    fn strict_unknown_type() {
+        let dir = std::env::temp_dir().join(format!("ta-unknown-type-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("transactions.csv");
+        fs::write(
+            &file,
+            "type, client, tx, amount\ndeposit, 1, 1, 1.0\ntransfer, 1, 2, 1.0\ndeposit, 1, 3, 1.0\n",
+        )
+        .unwrap();
+
+        let mut lenient = Engine::new();
+        let lenient_stats = lenient.read_file(&file);
+        let mut strict = Engine::builder().strict(true).build();
+        let strict_res = strict.read_file(&file);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            lenient_stats.unwrap(),
+            ProcessStats {
+                applied: 2,
+                skipped: 1,
+                errors: 0,
+            }
+        );
+        assert!(lenient.client(1).unwrap().total == dec!(2.0));
+
+        assert!(matches!(
+            strict_res,
+            Err(EngineError::Parse(ParseError::UnknownType { ref kind, .. })) if kind == "transfer"
+        ));
+        assert!(strict.client(1).unwrap().total == dec!(1.0));
+    }
+
+    #[test]
+    fn read_wide_client_ids() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 70000, 1, 2.0
+deposit, 4294967295, 2, 1.0
+withdrawal, 70000, 3, 0.5";
+
+        engine.from_str(csv).unwrap();
+        assert!(engine.client(70000).unwrap().total == dec!(1.5));
+        assert!(engine.client(4294967295).unwrap().total == dec!(1.0));
+
+        let mut out = Vec::new();
+        engine.write_clients(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "client,available,held,total,locked
+70000,1.5000,0.0000,1.5000,false
+4294967295,1.0000,0.0000,1.0000,false
+"
+        );
+    }
+
+    #[test]
+    fn parse_out_of_range_ids() {
+        let engine = Engine::new();
+
+        let record = StringRecord::from(vec!["deposit", "4294967296", "1", "1.0"]);
+        let err = engine.parse_record(&record).unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::OutOfRange {
+                line: "deposit,4294967296,1,1.0".to_string(),
+                field: "client",
+                value: "4294967296".to_string(),
+                ty: "u32",
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"value "4294967296" for field `client` is out of range for u32 in record: "deposit,4294967296,1,1.0""#
+        );
+
+        let record = StringRecord::from(vec!["dispute", "1", "4294967296", ""]);
+        assert!(matches!(
+            engine.parse_record(&record),
+            Err(ParseError::OutOfRange {
+                field: "tx",
+                ty: "u32",
+                ..
+            })
+        ));
+
+        let record = StringRecord::from(vec!["deposit", "-1", "1", "1.0"]);
+        assert!(matches!(
+            engine.parse_record(&record),
+            Err(ParseError::InvalidField {
+                field: "client",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_thousands_separators() {
+        let record = StringRecord::from(vec!["deposit", "1", "1", "1,000.50"]);
+
+        let engine = Engine::new();
+        assert_eq!(
+            engine.parse_record(&record),
+            Err(ParseError::InvalidField {
+                line: "deposit,1,1,1,000.50".to_string(),
+                field: "amount",
+                value: "1,000.50".to_string(),
+            })
+        );
+
+        let engine = Engine::builder().thousands_separators(true).build();
+        assert_eq!(
+            engine.parse_record(&record),
+            Ok(Transaction::Deposit(1, 1, dec!(1000.50)))
+        );
+        let misgrouped = StringRecord::from(vec!["deposit", "1", "1", "10,00.50"]);
+        assert!(matches!(
+            engine.parse_record(&misgrouped),
+            Err(ParseError::InvalidField {
+                field: "amount",
+                ..
+            })
+        ));
+
+        let mut engine = Engine::builder().thousands_separators(true).build();
+        let csv = "type, client, tx, amount
+deposit,1,1,\"1,234,567.5\"";
+        engine.from_str(csv).unwrap();
+        assert!(engine.client(1).unwrap().total == dec!(1234567.5));
+    }
+
+    #[test]
+    fn read_unquoted_thousands_separators() {
+        let csv = "type, client, tx, amount
+deposit,1,1,1,000.00
+deposit,1,2,2,345,678.5
+deposit,1,3,5.0";
+
+        for mut engine in [
+            Engine::new(),
+            Engine::builder().thousands_separators(true).build(),
+        ] {
+            let stats = engine.from_str(csv).unwrap();
+            assert_eq!(stats.applied, 1);
+            assert_eq!(stats.skipped, 2);
+            assert!(engine.client(1).unwrap().total == dec!(5.0));
+        }
+
+        let report = Engine::new().validate_reader(csv.as_bytes()).unwrap();
+        assert_eq!(report.issues.len(), 2);
+        assert!(matches!(
+            &report.issues[0],
+            (1, EngineError::Parse(ParseError::InvalidField { field: "amount", value, .. }))
+                if value == "1,000.00"
+        ));
+        assert!(matches!(
+            &report.issues[1],
+            (2, EngineError::Parse(ParseError::InvalidField { field: "amount", value, .. }))
+                if value == "2,345,678.5"
+        ));
+
+        let mut engine = Engine::builder().strict(true).build();
+        assert!(matches!(
+            engine.from_str(csv),
+            Err(EngineError::Parse(ParseError::InvalidField { line, field: "amount", value }))
+                if line == "deposit,1,1,1,000.00" && value == "1,000.00"
+        ));
+
+        let mut engine = Engine::builder().has_headers(false).build();
+        let stats = engine
+            .from_str("deposit,1,1,1,000.00\ndeposit,1,2,1.0,comment")
+            .unwrap();
+        assert_eq!(stats.applied, 1);
+        assert_eq!(stats.skipped, 1);
+        assert!(engine.client(1).unwrap().total == dec!(1.0));
+    }
+
+    #[test]
+    fn read_extra_three_digit_column() {
+        let csv = "type, client, tx, amount
+deposit,1,1,5.0,123
+deposit,1,2,1000,123.5
+withdrawal,1,3,1.5,250";
+
+        let mut engine = Engine::new();
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.applied, 3);
+        assert_eq!(stats.skipped, 0);
+        assert!(engine.client(1).unwrap().total == dec!(1003.5));
+    }
+
+    #[test]
+    fn parse_column_count() {
+        let engine = Engine::new();
+
+        let extra = StringRecord::from(vec!["deposit", "1", "1", "1.0", "comment"]);
+        assert_eq!(
+            engine.parse_record(&extra),
+            Ok(Transaction::Deposit(1, 1, dec!(1.0)))
+        );
+
+        let short = StringRecord::from(vec!["deposit", "1"]);
+        assert_eq!(
+            engine.parse_record(&short),
+            Err(ParseError::MissingField {
+                line: "deposit,1".to_string(),
+                field: "tx",
+            })
+        );
+
+        let mut engine = Engine::new();
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 1.0, comment
+deposit, 1
+deposit, 1, 2, 2.0";
+        let stats = engine.from_str(csv).unwrap();
+        assert_eq!(stats.applied, 2);
+        assert_eq!(stats.skipped, 1);
+        assert!(engine.client(1).unwrap().total == dec!(3.0));
+    }
+
+    #[test]
+    fn parse_malformed_records() {
+        let engine = Engine::new();
+
+        let missing_amount = StringRecord::from(vec!["deposit", "1", "1", ""]);
+        assert_eq!(
+            engine.parse_record(&missing_amount),
+            Err(ParseError::MissingField {
+                line: "deposit,1,1,".to_string(),
+                field: "amount",
+            })
+        );
+
+        let bad_client = StringRecord::from(vec!["withdrawal", "abc", "1", "1.0"]);
+        assert_eq!(
+            engine.parse_record(&bad_client),
+            Err(ParseError::InvalidField {
+                line: "withdrawal,abc,1,1.0".to_string(),
+                field: "client",
+                value: "abc".to_string(),
+            })
+        );
+
+        let unknown = StringRecord::from(vec!["transfer", "1", "1", "1.0"]);
+        assert!(matches!(
+            engine.parse_record(&unknown),
+            Err(ParseError::UnknownType { .. })
+        ));
+
+        let too_precise = StringRecord::from(vec!["deposit", "1", "1", "1.00001"]);
+        assert!(matches!(
+            engine.parse_record(&too_precise),
+            Err(ParseError::TooPrecise { .. })
+        ));
+
+        let empty = StringRecord::new();
+        assert!(matches!(
+            engine.parse_record(&empty),
+            Err(ParseError::MissingField { field: "type", .. })
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_amounts() {
+        let engine = Engine::new();
+
+        let negative = StringRecord::from(vec!["deposit", "1", "1", "-50.0"]);
+        assert_eq!(
+            engine.parse_record(&negative),
+            Err(ParseError::NegativeAmount {
+                line: "deposit,1,1,-50.0".to_string(),
+                value: "-50.0".to_string(),
+            })
+        );
+
+        let nan = StringRecord::from(vec!["withdrawal", "1", "1", "NaN"]);
+        assert!(matches!(
+            engine.parse_record(&nan),
+            Err(ParseError::InvalidField {
+                field: "amount",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parse_non_finite_amounts() {
+        let engine = Engine::new();
+
+        for value in ["inf", "-inf", "nan", "NaN", "infinity", "+Inf"] {
+            let record = StringRecord::from(vec!["deposit", "1", "1", value]);
+            assert_eq!(
+                engine.parse_record(&record),
+                Err(ParseError::InvalidField {
+                    line: format!("deposit,1,1,{}", value),
+                    field: "amount",
+                    value: value.to_string(),
+                }),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn skip_malformed_row() {
+        let mut engine = Engine::new();
+
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+deposit, x, 2, 1.0
+deposit, 1, 3, 3.0";
+
+        let res = engine.from_str(csv);
+        assert!(res.is_ok());
+        assert!(engine.client(1).unwrap().available == dec!(5.0));
+        assert!(engine.client(1).unwrap().total == dec!(5.0));
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt;
+use std::io;
+
+use crate::ClientId;
+
+/// Error returned when a csv record can not be parsed into a Transaction
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    /// The record is missing a field required by its transaction type
+    MissingField { line: String, field: &'static str },
+    /// A field could not be parsed into the expected type
+    InvalidField {
+        line: String,
+        field: &'static str,
+        value: String,
+    },
+    /// An amount is negative
+    NegativeAmount { line: String, value: String },
+    /// An amount has more decimal places than the configured scale
+    TooPrecise {
+        line: String,
+        value: String,
+        scale: u32,
+    },
+    /// The transaction type is not one of the known types
+    UnknownType { line: String, kind: String },
+    /// An id is too large for its integer type
+    OutOfRange {
+        line: String,
+        field: &'static str,
+        value: String,
+        ty: &'static str,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingField { line, field } => {
+                write!(f, "missing field `{}` in record: {:?}", field, line)
+            }
+            ParseError::InvalidField { line, field, value } => write!(
+                f,
+                "invalid value {:?} for field `{}` in record: {:?}",
+                value, field, line
+            ),
+            ParseError::NegativeAmount { line, value } => {
+                write!(f, "negative amount {:?} in record: {:?}", value, line)
+            }
+            ParseError::TooPrecise { line, value, scale } => write!(
+                f,
+                "amount {:?} has more than {} decimal places in record: {:?}",
+                value, scale, line
+            ),
+            ParseError::UnknownType { line, kind } => {
+                write!(
+                    f,
+                    "unknown transaction type {:?} in record: {:?}",
+                    kind, line
+                )
+            }
+            ParseError::OutOfRange {
+                line,
+                field,
+                value,
+                ty,
+            } => write!(
+                f,
+                "value {:?} for field `{}` is out of range for {} in record: {:?}",
+                value, field, ty, line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Error returned by the engine
+#[derive(Debug)]
+pub enum EngineError {
+    /// Reading the input failed
+    Io(io::Error),
+    /// The input is not valid csv
+    #[cfg(feature = "csv")]
+    Csv(csv::Error),
+    /// The async input is not valid csv
+    #[cfg(feature = "async")]
+    AsyncCsv(csv_async::Error),
+    /// A record could not be parsed into a Transaction
+    Parse(ParseError),
+    /// A withdrawal exceeds the client's available funds
+    InsufficientFunds { client: ClientId, tx: u32 },
+    /// A transaction reuses the id of an already processed transaction
+    DuplicateTransaction { client: ClientId, tx: u32 },
+    /// A transaction or dispute would overflow the client's balance
+    Overflow { client: ClientId, tx: u32 },
+    /// Merging engines would overflow the summed balances of a client
+    MergeOverflow { client: ClientId },
+    /// An amount has a different number of decimal places than the client's earlier amounts
+    ScaleMismatch {
+        client: ClientId,
+        tx: u32,
+        scale: u32,
+        expected: u32,
+    },
+    /// The input has more records than the configured maximum
+    LimitExceeded { limit: usize },
+    /// The csv header is missing required columns
+    MissingColumns(Vec<&'static str>),
+}
+
+impl EngineError {
+    /// The level a record rejected with this error is logged at
+    ///
+    /// Overflows and scale mismatches are logged as errors, other rejections as warnings.
+    pub(crate) fn level(&self) -> log::Level {
+        match self {
+            EngineError::Overflow { .. }
+            | EngineError::MergeOverflow { .. }
+            | EngineError::ScaleMismatch { .. } => log::Level::Error,
+            _ => log::Level::Warn,
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Io(err) => write!(f, "io error: {}", err),
+            #[cfg(feature = "csv")]
+            EngineError::Csv(err) => write!(f, "csv error: {}", err),
+            #[cfg(feature = "async")]
+            EngineError::AsyncCsv(err) => write!(f, "csv error: {}", err),
+            EngineError::Parse(err) => write!(f, "parse error: {}", err),
+            EngineError::InsufficientFunds { client, tx } => write!(
+                f,
+                "insufficient funds for withdrawal {} by client {}",
+                tx, client
+            ),
+            EngineError::DuplicateTransaction { client, tx } => write!(
+                f,
+                "transaction id {} of client {} has already been processed",
+                tx, client
+            ),
+            EngineError::Overflow { client, tx } => write!(
+                f,
+                "transaction {} would overflow the balance of client {}",
+                tx, client
+            ),
+            EngineError::MergeOverflow { client } => {
+                write!(f, "merging would overflow the balance of client {}", client)
+            }
+            EngineError::ScaleMismatch {
+                client,
+                tx,
+                scale,
+                expected,
+            } => write!(
+                f,
+                "transaction {} of client {} has {} decimal places, expected {}",
+                tx, client, scale, expected
+            ),
+            EngineError::LimitExceeded { limit } => {
+                write!(f, "input exceeds the limit of {} transactions", limit)
+            }
+            EngineError::MissingColumns(columns) => {
+                write!(
+                    f,
+                    "csv header is missing the columns: {}",
+                    columns.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EngineError::Io(err) => Some(err),
+            #[cfg(feature = "csv")]
+            EngineError::Csv(err) => Some(err),
+            #[cfg(feature = "async")]
+            EngineError::AsyncCsv(err) => Some(err),
+            EngineError::Parse(err) => Some(err),
+            EngineError::InsufficientFunds { .. }
+            | EngineError::DuplicateTransaction { .. }
+            | EngineError::Overflow { .. }
+            | EngineError::MergeOverflow { .. }
+            | EngineError::ScaleMismatch { .. }
+            | EngineError::LimitExceeded { .. }
+            | EngineError::MissingColumns(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for EngineError {
+    fn from(err: io::Error) -> Self {
+        EngineError::Io(err)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for EngineError {
+    fn from(err: csv::Error) -> Self {
+        EngineError::Csv(err)
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<csv_async::Error> for EngineError {
+    fn from(err: csv_async::Error) -> Self {
+        EngineError::AsyncCsv(err)
+    }
+}
+
+impl From<ParseError> for EngineError {
+    fn from(err: ParseError) -> Self {
+        EngineError::Parse(err)
+    }
+}
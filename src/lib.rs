@@ -0,0 +1,2583 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod arbitrary;
+#[cfg(feature = "async")]
+mod async_io;
+#[cfg(feature = "bincode")]
+mod bincode_io;
+mod builder;
+#[cfg(feature = "csv")]
+mod csv_io;
+mod error;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod tee;
+#[cfg(test)]
+mod test_logger;
+
+pub use builder::EngineBuilder;
+#[cfg(feature = "csv")]
+pub use csv_io::{parse_record, ProcessStats, ValidationReport, WriteOptions};
+pub use error::{EngineError, ParseError};
+pub use tee::Tee;
+
+/// Number of decimal places amounts are stored and reported with
+pub const DECIMAL_PLACES: u32 = 4;
+
+/// The type of client ids
+///
+/// Wide enough for more than 65535 clients, ids are still parsed as plain unsigned integers.
+pub type ClientId = u32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transaction {
+    Deposit(ClientId, u32, Decimal),
+    Withdrawal(ClientId, u32, Decimal),
+    Dispute(ClientId, u32),
+    Resolve(ClientId, u32),
+    Chargeback(ClientId, u32),
+    Settle(ClientId, u32),
+    Freeze(ClientId),
+    Unfreeze(ClientId),
+}
+
+impl Transaction {
+    /// The id of the client the transaction belongs to
+    pub fn client(&self) -> ClientId {
+        match *self {
+            Transaction::Deposit(id, ..)
+            | Transaction::Withdrawal(id, ..)
+            | Transaction::Dispute(id, _)
+            | Transaction::Resolve(id, _)
+            | Transaction::Chargeback(id, _)
+            | Transaction::Settle(id, _)
+            | Transaction::Freeze(id)
+            | Transaction::Unfreeze(id) => id,
+        }
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, id, tx, amount) = match *self {
+            Transaction::Deposit(id, tx, amount) => ("deposit", id, Some(tx), Some(amount)),
+            Transaction::Withdrawal(id, tx, amount) => ("withdrawal", id, Some(tx), Some(amount)),
+            Transaction::Dispute(id, tx) => ("dispute", id, Some(tx), None),
+            Transaction::Resolve(id, tx) => ("resolve", id, Some(tx), None),
+            Transaction::Chargeback(id, tx) => ("chargeback", id, Some(tx), None),
+            Transaction::Settle(id, tx) => ("settle", id, Some(tx), None),
+            Transaction::Freeze(id) => ("freeze", id, None, None),
+            Transaction::Unfreeze(id) => ("unfreeze", id, None, None),
+        };
+        write!(f, "{} client={}", kind, id)?;
+        if let Some(tx) = tx {
+            write!(f, " tx={}", tx)?;
+        }
+        if let Some(amount) = amount {
+            write!(f, " amount={}", display_amount(amount))?;
+        }
+        Ok(())
+    }
+}
+
+/// The kind of a balance changing transaction
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    Deposit,
+    Withdrawal,
+}
+
+/// A processed deposit or withdrawal, kept for later disputes
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub client: ClientId,
+    pub amount: Decimal,
+    pub kind: TransactionKind,
+}
+
+/// Number of transactions of each type applied to a client
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransactionCounts {
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub disputes: u64,
+}
+
+/// What happened to a transaction handled by `Engine::handle_record`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessOutcome {
+    /// The transaction was applied
+    Applied,
+    /// A withdrawal was rejected because it exceeds the available funds
+    RejectedInsufficientFunds,
+    /// A deposit, withdrawal or dispute was rejected because it would overflow the client's
+    /// balance
+    Overflow,
+    /// The disputed transaction does not exist or belongs to another client
+    UnknownTx,
+    /// The client's account is locked
+    Locked,
+    /// The transaction id was already used, or the transaction is already under dispute
+    Duplicate,
+    /// A resolve or chargeback refers to a transaction that is not under dispute
+    NotDisputed,
+    /// A dispute refers to a withdrawal while withdrawal disputes are disabled
+    WithdrawalDispute,
+    /// A dispute exceeds the maximum number of open disputes per client
+    DisputeLimit,
+    /// A deposit or withdrawal has a different number of decimal places than the client's
+    /// earlier transactions
+    ScaleMismatch,
+}
+
+impl ProcessOutcome {
+    /// The outcome of a transaction rejected with `err`
+    fn rejected(err: &EngineError) -> Self {
+        match err {
+            EngineError::InsufficientFunds { .. } => ProcessOutcome::RejectedInsufficientFunds,
+            EngineError::DuplicateTransaction { .. } => ProcessOutcome::Duplicate,
+            EngineError::Overflow { .. } => ProcessOutcome::Overflow,
+            EngineError::ScaleMismatch { .. } => ProcessOutcome::ScaleMismatch,
+            err => unreachable!("unexpected error applying a transaction: {}", err),
+        }
+    }
+}
+
+impl fmt::Display for ProcessOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self {
+            ProcessOutcome::Applied => "applied",
+            ProcessOutcome::RejectedInsufficientFunds => "rejected, insufficient funds",
+            ProcessOutcome::Overflow => "rejected, overflow",
+            ProcessOutcome::UnknownTx => "ignored, unknown transaction",
+            ProcessOutcome::Locked => "ignored, account locked",
+            ProcessOutcome::Duplicate => "ignored, duplicate",
+            ProcessOutcome::NotDisputed => "ignored, not under dispute",
+            ProcessOutcome::WithdrawalDispute => "ignored, withdrawal disputes are disabled",
+            ProcessOutcome::DisputeLimit => "ignored, too many open disputes",
+            ProcessOutcome::ScaleMismatch => "rejected, scale mismatch",
+        };
+        f.write_str(outcome)
+    }
+}
+
+/// How withdrawals exceeding the available funds are handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverdraftPolicy {
+    /// Reject the withdrawal with `EngineError::InsufficientFunds`
+    #[default]
+    Reject,
+    /// Apply the withdrawal, leaving the client with negative available funds
+    Allow,
+}
+
+/// Aggregate statistics over all clients of an engine
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EngineStats {
+    /// Number of clients
+    pub clients: usize,
+    /// Number of locked clients
+    pub locked: usize,
+    /// Sum of the total funds of all clients, saturating at `Decimal::MIN` and `Decimal::MAX`
+    pub total: Decimal,
+    /// Number of transactions currently under dispute
+    pub open_disputes: usize,
+    /// Sum of all applied deposits, saturating at `Decimal::MAX`
+    pub deposited: Decimal,
+    /// Sum of all applied withdrawals, saturating at `Decimal::MAX`
+    pub withdrawn: Decimal,
+    /// Sum of the held funds of all clients, saturating at `Decimal::MIN` and `Decimal::MAX`
+    pub held: Decimal,
+    /// Sum of all charged back transactions, saturating at `Decimal::MAX`
+    pub charged_back: Decimal,
+}
+
+/// Running totals of the amounts processed by an engine, accumulated as transactions are applied
+///
+/// The totals span all clients, so they saturate at `Decimal::MAX` instead of rejecting a
+/// transaction that fits the client's own balance.
+#[derive(Clone, Copy, Debug, Default)]
+struct Totals {
+    deposited: Decimal,
+    withdrawn: Decimal,
+    charged_back: Decimal,
+}
+
+impl Totals {
+    fn add(&mut self, other: Totals) {
+        self.deposited = self.deposited.saturating_add(other.deposited);
+        self.withdrawn = self.withdrawn.saturating_add(other.withdrawn);
+        self.charged_back = self.charged_back.saturating_add(other.charged_back);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Client {
+    pub id: ClientId,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+    pub counts: TransactionCounts,
+    /// Decimal places of the client's amounts, set by its first deposit or withdrawal if
+    /// enabled with `EngineBuilder::fixed_client_scale`
+    #[serde(default)]
+    pub scale: Option<u32>,
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {}, {}, {}, {}",
+            self.id,
+            display_amount(self.available),
+            display_amount(self.held),
+            display_amount(self.total),
+            self.locked,
+        )
+    }
+}
+
+impl Client {
+    pub fn new(id: ClientId) -> Self {
+        Self {
+            id,
+            available: Decimal::ZERO,
+            held: Decimal::ZERO,
+            total: Decimal::ZERO,
+            locked: false,
+            counts: TransactionCounts::default(),
+            scale: None,
+        }
+    }
+
+    /// Check if the client is unlocked and has no available, held or total funds
+    pub fn is_empty(&self) -> bool {
+        !self.locked && self.available.is_zero() && self.held.is_zero() && self.total.is_zero()
+    }
+
+    /// Check that the total funds equal the available and held funds
+    pub fn is_consistent(&self) -> bool {
+        self.total == self.available + self.held
+    }
+}
+
+/// A client as written by `Engine::write_clients_json`
+///
+/// Amounts are rounded like the csv output and written as strings so no precision is lost to
+/// floating point numbers.
+#[derive(Debug, Serialize)]
+struct ClientOutput {
+    id: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+}
+
+impl ClientOutput {
+    fn new(client: &Client, precision: u32) -> Self {
+        Self {
+            id: client.id,
+            available: format_amount(client.available, precision),
+            held: format_amount(client.held, precision),
+            total: format_amount(client.total, precision),
+            locked: client.locked,
+        }
+    }
+}
+
+/// Round an amount to `precision` decimal places for output
+///
+/// Midpoints are rounded to the nearest even digit (banker's rounding). Amounts that round to
+/// zero, including negative zero, are written as positive zero.
+fn format_amount(amount: Decimal, precision: u32) -> String {
+    let rounded = amount.round_dp_with_strategy(precision, RoundingStrategy::MidpointNearestEven);
+    let rounded = if rounded.is_zero() {
+        Decimal::ZERO
+    } else {
+        rounded
+    };
+    // Padded by hand, as formatting with a precision fails for amounts near `Decimal::MAX`
+    let zeros = "0".repeat(precision.saturating_sub(rounded.scale()) as usize);
+    match (zeros.is_empty(), rounded.scale()) {
+        (true, _) => rounded.to_string(),
+        (false, 0) => format!("{}.{}", rounded, zeros),
+        (false, _) => format!("{}{}", rounded, zeros),
+    }
+}
+
+/// Format an amount for display with at least `DECIMAL_PLACES` decimal places
+///
+/// Amounts with more decimal places, such as those parsed with a larger `EngineBuilder::scale`,
+/// are written at their own scale instead of being rounded.
+fn display_amount(amount: Decimal) -> String {
+    format_amount(amount, amount.scale().max(DECIMAL_PLACES))
+}
+
+/// The state of an engine, for persisting and later restoring it
+///
+/// Only the clients, transactions and disputes are captured, not the engine's configuration.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    pub clients: Vec<Client>,
+    pub transactions: HashMap<u32, TransactionRecord>,
+    pub disputes: HashSet<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Engine {
+    transactions: HashMap<u32, TransactionRecord>,
+    clients: HashMap<ClientId, Client>,
+    disbutes: HashSet<u32>,
+    /// The last applied deposit or withdrawal of each client and the index of its history entry,
+    /// if recorded, for `undo_last`
+    last_tx: HashMap<ClientId, (u32, Option<usize>)>,
+    precision: u32,
+    warn_out_of_order: bool,
+    allow_withdrawal_disputes: bool,
+    fixed_client_scale: bool,
+    max_open_disputes: Option<usize>,
+    overdraft_policy: OverdraftPolicy,
+    track_history: bool,
+    /// Available funds of each client after every applied transaction, if tracked
+    history: HashMap<ClientId, Vec<Decimal>>,
+    totals: Totals,
+    /// Highest deposit or withdrawal id seen, tracked when warning about out of order ids
+    max_tx: Option<u32>,
+    #[cfg(feature = "csv")]
+    strict: bool,
+    #[cfg(feature = "csv")]
+    delimiter: u8,
+    #[cfg(feature = "csv")]
+    has_headers: bool,
+    #[cfg(feature = "csv")]
+    thousands_separators: bool,
+    #[cfg(feature = "csv")]
+    max_transactions: Option<usize>,
+    #[cfg(feature = "csv")]
+    order_by_time: bool,
+    #[cfg(feature = "csv")]
+    skip_csv_errors: bool,
+    #[cfg(feature = "csv")]
+    column_names: HashMap<String, String>,
+    #[cfg(feature = "csv")]
+    scale: u32,
+    audit: AuditLog,
+    callback: RecordCallback,
+}
+
+/// An optional sink receiving one line per processed transaction
+///
+/// Clones of an engine do not share the sink, so a cloned engine starts without an audit log.
+#[derive(Default)]
+struct AuditLog(Option<Box<dyn Write + Send>>);
+
+impl AuditLog {
+    /// Write the outcome of a transaction to the sink, if set
+    fn record(&mut self, record: &Transaction, result: &Result<ProcessOutcome, EngineError>) {
+        let Some(sink) = self.0.as_mut() else {
+            return;
+        };
+        let written = match result {
+            Ok(outcome) => writeln!(sink, "{}: {}", record, outcome),
+            Err(err) => writeln!(sink, "{}: rejected, {}", record, err),
+        };
+        if let Err(err) = written {
+            log::warn!("Failed to write audit log: {}", err);
+        }
+    }
+}
+
+impl Clone for AuditLog {
+    fn clone(&self) -> Self {
+        AuditLog(None)
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AuditLog")
+            .field(&self.0.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+/// An optional callback invoked with the outcome of every processed transaction
+///
+/// Like the audit log, the callback is not shared with clones of an engine.
+#[derive(Default)]
+struct RecordCallback(Option<Box<Callback>>);
+
+type Callback = dyn FnMut(&Transaction, ProcessOutcome) + Send;
+
+impl Clone for RecordCallback {
+    fn clone(&self) -> Self {
+        RecordCallback(None)
+    }
+}
+
+impl fmt::Debug for RecordCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RecordCallback")
+            .field(&self.0.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        EngineBuilder::new().build()
+    }
+}
+
+/// Log a resolve, chargeback or settle of a transaction that is not under dispute
+///
+/// Such records are ignored, but most likely point to an error in the input data.
+fn not_disputed(action: &str, id: ClientId, tx: u32) -> ProcessOutcome {
+    log::warn!(
+        "Ignoring {} of transaction {} by client {}, it is not under dispute",
+        action,
+        tx,
+        id
+    );
+    ProcessOutcome::NotDisputed
+}
+
+impl Engine {
+    /// Create a new engine
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder for configuring an engine
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    /// Create a new engine with the given clients and their balances
+    ///
+    /// The engine has no transactions, so only transactions processed afterwards can be disputed.
+    pub fn with_clients(clients: Vec<Client>) -> Engine {
+        let mut engine = Engine::new();
+        engine.clients = clients
+            .into_iter()
+            .map(|client| (client.id, client))
+            .collect();
+        engine
+    }
+
+    /// Set the number of decimal places used when writing client balances
+    ///
+    /// This only affects output, amounts are still parsed with up to the configured scale of
+    /// decimals.
+    pub fn set_precision(&mut self, precision: u32) {
+        self.precision = precision;
+    }
+
+    /// Write the outcome of every processed transaction to `sink`, one line each
+    ///
+    /// Each line holds the transaction followed by its outcome: applied, ignored, for example a
+    /// dispute of an unknown transaction, or rejected, along with the reason.
+    pub fn set_audit_log(&mut self, sink: Box<dyn Write + Send>) {
+        self.audit = AuditLog(Some(sink));
+    }
+
+    /// Register a callback invoked with every processed transaction and its outcome
+    ///
+    /// The callback is invoked for transactions handled with `handle_record` as well as records
+    /// read from csv input, and replaces any previously registered callback.
+    pub fn on_record<F: FnMut(&Transaction, ProcessOutcome) + Send + 'static>(&mut self, f: F) {
+        self.callback = RecordCallback(Some(Box::new(f)));
+    }
+
+    /// Handle a record or transaction, returning what happened to it
+    ///
+    /// Rejected transactions are logged as warnings, or as errors if they would overflow a balance
+    /// or do not match the client's scale. In debug builds the affected client is checked to be
+    /// consistent afterwards.
+    pub fn handle_record(&mut self, record: Transaction) -> ProcessOutcome {
+        let outcome = match self.apply(record) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                log::log!(err.level(), "Rejected transaction {}: {}", record, err);
+                ProcessOutcome::rejected(&err)
+            }
+        };
+        debug_assert!(
+            self.clients
+                .get(&record.client())
+                .is_none_or(Client::is_consistent),
+            "inconsistent balances after {}",
+            record
+        );
+        outcome
+    }
+
+    /// Handle a batch of transactions in order
+    pub fn handle_all(&mut self, txs: &[Transaction]) {
+        for tx in txs {
+            self.handle_record(*tx);
+        }
+    }
+
+    /// Apply a transaction, returning an error if it was rejected
+    ///
+    /// The outcome is written to the audit log and passed to the record callback, if set.
+    fn apply(&mut self, record: Transaction) -> Result<ProcessOutcome, EngineError> {
+        let result = match record {
+            Transaction::Deposit(id, tx, amount) => {
+                self.transaction(id, tx, TransactionKind::Deposit, amount)
+            }
+            Transaction::Withdrawal(id, tx, amount) => {
+                self.transaction(id, tx, TransactionKind::Withdrawal, amount)
+            }
+            Transaction::Dispute(id, tx) => self.try_dispute(id, tx),
+            Transaction::Resolve(id, tx) => Ok(self.resolve(id, tx)),
+            Transaction::Chargeback(id, tx) => Ok(self.chargeback(id, tx)),
+            Transaction::Settle(id, tx) => Ok(self.settle(id, tx)),
+            Transaction::Freeze(id) => Ok(self.freeze(id)),
+            Transaction::Unfreeze(id) => Ok(self.unfreeze(id)),
+        };
+        self.audit.record(&record, &result);
+        if self.track_history && matches!(result, Ok(ProcessOutcome::Applied)) {
+            let id = record.client();
+            if let Some(client) = self.clients.get(&id) {
+                let history = self.history.entry(id).or_default();
+                history.push(client.available);
+                if let Transaction::Deposit(_, tx, _) | Transaction::Withdrawal(_, tx, _) = record {
+                    self.last_tx.insert(id, (tx, Some(history.len() - 1)));
+                }
+            }
+        }
+        if let Some(callback) = self.callback.0.as_mut() {
+            let outcome = match &result {
+                Ok(outcome) => *outcome,
+                Err(err) => ProcessOutcome::rejected(err),
+            };
+            callback(&record, outcome);
+        }
+        result
+    }
+
+    /// Apply a deposit or withdrawal of `amount` to a client
+    ///
+    /// Transaction ids are globally unique, a transaction reusing the id of an already processed
+    /// transaction is rejected, even if the earlier transaction belongs to another client. A
+    /// withdrawal larger than the client's available funds is rejected, unless overdrafts are
+    /// allowed by the `OverdraftPolicy`, as is a deposit that would overflow the client's balance.
+    /// Transactions on a locked account are ignored. A client is only created by its first applied
+    /// transaction, so a rejected transaction of an unknown client does not create it.
+    ///
+    /// If enabled with `EngineBuilder::fixed_client_scale`, a transaction whose amount has a
+    /// different number of decimal places than the client's first transaction is rejected.
+    pub fn transaction(
+        &mut self,
+        id: ClientId,
+        tx: u32,
+        kind: TransactionKind,
+        amount: Decimal,
+    ) -> Result<ProcessOutcome, EngineError> {
+        if self.warn_out_of_order {
+            match self.max_tx {
+                Some(max) if tx < max => log::warn!(
+                    "Transaction {} is lower than the previous maximum id {}, the input may be out of order",
+                    tx,
+                    max
+                ),
+                _ => self.max_tx = Some(tx),
+            }
+        }
+        if self.transactions.contains_key(&tx) {
+            return Err(EngineError::DuplicateTransaction { client: id, tx });
+        }
+        let mut client = self.clients.get(&id).copied().unwrap_or(Client::new(id));
+        if client.locked {
+            return Ok(ProcessOutcome::Locked);
+        }
+        match client.scale {
+            Some(expected) if self.fixed_client_scale && amount.scale() != expected => {
+                return Err(EngineError::ScaleMismatch {
+                    client: id,
+                    tx,
+                    scale: amount.scale(),
+                    expected,
+                });
+            }
+            _ => {}
+        }
+        let delta = match kind {
+            TransactionKind::Deposit => amount,
+            TransactionKind::Withdrawal
+                if client.available < amount
+                    && self.overdraft_policy == OverdraftPolicy::Reject =>
+            {
+                return Err(EngineError::InsufficientFunds { client: id, tx });
+            }
+            TransactionKind::Withdrawal => -amount,
+        };
+        match (
+            client.total.checked_add(delta),
+            client.available.checked_add(delta),
+        ) {
+            (Some(total), Some(available)) => {
+                client.total = total;
+                client.available = available;
+            }
+            _ => return Err(EngineError::Overflow { client: id, tx }),
+        }
+        match kind {
+            TransactionKind::Deposit => client.counts.deposits += 1,
+            TransactionKind::Withdrawal => client.counts.withdrawals += 1,
+        }
+        if self.fixed_client_scale && client.scale.is_none() {
+            client.scale = Some(amount.scale());
+        }
+        self.clients.insert(id, client);
+        self.transactions.insert(
+            tx,
+            TransactionRecord {
+                client: id,
+                amount,
+                kind,
+            },
+        );
+        match kind {
+            TransactionKind::Deposit => {
+                self.totals.deposited = self.totals.deposited.saturating_add(amount)
+            }
+            TransactionKind::Withdrawal => {
+                self.totals.withdrawn = self.totals.withdrawn.saturating_add(amount)
+            }
+        }
+        self.last_tx.insert(id, (tx, None));
+        Ok(ProcessOutcome::Applied)
+    }
+
+    /// Revert the last applied deposit or withdrawal of a client
+    ///
+    /// The balances are restored and the transaction is forgotten, so its id can be reused. Only
+    /// the most recent transaction can be reverted, and not if it is under dispute, the account is
+    /// locked or, for a deposit, the funds have already been spent. Returns whether a transaction
+    /// was reverted.
+    ///
+    /// If enabled with `EngineBuilder::track_history`, the transaction's history entry is removed
+    /// and later entries are adjusted as if it had never been applied.
+    pub fn undo_last(&mut self, id: ClientId) -> bool {
+        let Some(&(tx, entry)) = self.last_tx.get(&id) else {
+            return false;
+        };
+        let (Some(record), Some(client)) = (self.disputable(id, tx), self.clients.get_mut(&id))
+        else {
+            return false;
+        };
+        if client.locked || self.disbutes.contains(&tx) {
+            return false;
+        }
+        let delta = match record.kind {
+            TransactionKind::Deposit if client.available < record.amount => return false,
+            TransactionKind::Deposit => {
+                client.available -= record.amount;
+                client.total -= record.amount;
+                client.counts.deposits -= 1;
+                self.totals.deposited -= record.amount;
+                record.amount
+            }
+            TransactionKind::Withdrawal => {
+                client.available += record.amount;
+                client.total += record.amount;
+                client.counts.withdrawals -= 1;
+                self.totals.withdrawn -= record.amount;
+                -record.amount
+            }
+        };
+        if let (Some(index), Some(history)) = (entry, self.history.get_mut(&id)) {
+            if index < history.len() {
+                history.remove(index);
+                for available in &mut history[index..] {
+                    *available -= delta;
+                }
+            }
+        }
+        self.transactions.remove(&tx);
+        self.last_tx.remove(&id);
+        true
+    }
+
+    /// Look up a transaction owned by client `id`
+    ///
+    /// A transaction can only be disputed by the client that made it.
+    fn disputable(&self, id: ClientId, tx: u32) -> Option<TransactionRecord> {
+        self.transactions
+            .get(&tx)
+            .filter(|record| record.client == id)
+            .copied()
+    }
+
+    /// Dispute a transaction
+    ///
+    /// The disputed amount is moved to held funds. For a deposit it is taken from the available
+    /// funds, for a withdrawal it is provisionally credited back so the total increases.
+    /// Disputing a transaction that is already under dispute has no effect.
+    ///
+    /// Available funds are not clamped: disputing a deposit that has already been partly spent
+    /// leaves the client with negative available funds, while the total stays unchanged.
+    ///
+    /// Disputes of withdrawals are ignored if disabled with
+    /// `EngineBuilder::allow_withdrawal_disputes`, and disputes of a client with too many open
+    /// disputes if limited with `EngineBuilder::max_open_disputes_per_client`. A dispute that would
+    /// overflow the client's balances is rejected.
+    pub fn dispute(&mut self, id: ClientId, tx: u32) -> ProcessOutcome {
+        self.try_dispute(id, tx).unwrap_or_else(|err| {
+            log::log!(
+                err.level(),
+                "Rejected dispute of transaction {} by client {}: {}",
+                tx,
+                id,
+                err
+            );
+            ProcessOutcome::rejected(&err)
+        })
+    }
+
+    /// Dispute a transaction, returning an error if it would overflow the client's balances
+    fn try_dispute(&mut self, id: ClientId, tx: u32) -> Result<ProcessOutcome, EngineError> {
+        if self.disbutes.contains(&tx) {
+            return Ok(ProcessOutcome::Duplicate);
+        }
+        let Some(record) = self.disputable(id, tx) else {
+            return Ok(ProcessOutcome::UnknownTx);
+        };
+        if record.kind == TransactionKind::Withdrawal && !self.allow_withdrawal_disputes {
+            log::warn!(
+                "Ignoring dispute of withdrawal {} by client {}, withdrawal disputes are disabled",
+                tx,
+                id
+            );
+            return Ok(ProcessOutcome::WithdrawalDispute);
+        }
+        if let Some(max) = self.max_open_disputes {
+            if self.open_disputes_of(id) >= max {
+                log::warn!(
+                    "Ignoring dispute of transaction {} by client {}, {} disputes are already open",
+                    tx,
+                    id,
+                    max
+                );
+                return Ok(ProcessOutcome::DisputeLimit);
+            }
+        }
+        let client = match self.clients.get_mut(&id) {
+            None => return Ok(ProcessOutcome::UnknownTx),
+            Some(client) if client.locked => return Ok(ProcessOutcome::Locked),
+            Some(client) => client,
+        };
+        let (available, total) = match record.kind {
+            TransactionKind::Deposit => (
+                client.available.checked_sub(record.amount),
+                Some(client.total),
+            ),
+            TransactionKind::Withdrawal => (
+                Some(client.available),
+                client.total.checked_add(record.amount),
+            ),
+        };
+        match (available, total, client.held.checked_add(record.amount)) {
+            (Some(available), Some(total), Some(held)) => {
+                client.available = available;
+                client.total = total;
+                client.held = held;
+            }
+            _ => return Err(EngineError::Overflow { client: id, tx }),
+        }
+        client.counts.disputes += 1;
+        self.disbutes.insert(tx);
+        Ok(ProcessOutcome::Applied)
+    }
+
+    /// Count the open disputes of a client
+    fn open_disputes_of(&self, id: ClientId) -> usize {
+        self.disbutes
+            .iter()
+            .filter(|tx| self.transactions.get(tx).is_some_and(|r| r.client == id))
+            .count()
+    }
+
+    /// Resolve a dispute
+    ///
+    /// The disputed transaction stands and the client's balances return to their state before the
+    /// dispute.
+    pub fn resolve(&mut self, id: ClientId, tx: u32) -> ProcessOutcome {
+        let Some(record) = self.disputable(id, tx) else {
+            return ProcessOutcome::UnknownTx;
+        };
+        match self.clients.get_mut(&id) {
+            None => ProcessOutcome::UnknownTx,
+            Some(client) if client.locked => ProcessOutcome::Locked,
+            Some(_) if !self.disbutes.contains(&tx) => not_disputed("resolve", id, tx),
+            Some(client) => {
+                match record.kind {
+                    TransactionKind::Deposit => client.available += record.amount,
+                    TransactionKind::Withdrawal => client.total -= record.amount,
+                }
+                client.held -= record.amount;
+                self.disbutes.remove(&tx);
+                ProcessOutcome::Applied
+            }
+        }
+    }
+
+    /// Resolve all open disputes of a client, returning how many were resolved
+    ///
+    /// Disputes are resolved in ascending transaction id order. Nothing is resolved if the
+    /// client's account is locked.
+    pub fn resolve_all(&mut self, id: ClientId) -> usize {
+        let mut disputed: Vec<u32> = self
+            .disbutes
+            .iter()
+            .copied()
+            .filter(|tx| self.transactions.get(tx).is_some_and(|r| r.client == id))
+            .collect();
+        disputed.sort_unstable();
+        disputed
+            .into_iter()
+            .filter(|&tx| self.resolve(id, tx) == ProcessOutcome::Applied)
+            .count()
+    }
+
+    /// Handle a chargeback
+    ///
+    /// A chargeback is the final state of a dispute and represents the client reversing a transaction.
+    /// Funds that were held have now been withdrawn. This means that the clients held funds and
+    /// total funds should decrease by the amount previously disputed. If a chargeback occurs the
+    /// client's account should be immediately frozen.
+    ///
+    /// Reversing a withdrawal instead credits the held funds back to the client's available funds.
+    ///
+    /// Balances are not clamped: charging back a deposit that has already been partly withdrawn
+    /// leaves the client with negative available and total funds, the amount the client owes.
+    pub fn chargeback(&mut self, id: ClientId, tx: u32) -> ProcessOutcome {
+        let Some(record) = self.disputable(id, tx) else {
+            return ProcessOutcome::UnknownTx;
+        };
+        match self.clients.get_mut(&id) {
+            None => ProcessOutcome::UnknownTx,
+            Some(client) if client.locked => ProcessOutcome::Locked,
+            Some(_) if !self.disbutes.contains(&tx) => not_disputed("chargeback", id, tx),
+            Some(client) => {
+                match record.kind {
+                    TransactionKind::Deposit => client.total -= record.amount,
+                    TransactionKind::Withdrawal => client.available += record.amount,
+                }
+                client.held -= record.amount;
+                client.locked = true;
+                self.totals.charged_back = self.totals.charged_back.saturating_add(record.amount);
+                self.disbutes.remove(&tx);
+                ProcessOutcome::Applied
+            }
+        }
+    }
+
+    /// Settle a disputed transaction
+    ///
+    /// An administrative operation that ends the dispute by moving the held funds to the client's
+    /// available funds, without locking the account. For a deposit this is the same as a resolve,
+    /// for a withdrawal the funds credited back by the dispute stay with the client.
+    pub fn settle(&mut self, id: ClientId, tx: u32) -> ProcessOutcome {
+        let Some(record) = self.disputable(id, tx) else {
+            return ProcessOutcome::UnknownTx;
+        };
+        match self.clients.get_mut(&id) {
+            None => ProcessOutcome::UnknownTx,
+            Some(client) if client.locked => ProcessOutcome::Locked,
+            Some(_) if !self.disbutes.contains(&tx) => not_disputed("settle", id, tx),
+            Some(client) => {
+                client.available += record.amount;
+                client.held -= record.amount;
+                self.disbutes.remove(&tx);
+                ProcessOutcome::Applied
+            }
+        }
+    }
+
+    /// Lock a client's account
+    ///
+    /// An administrative operation, the account is locked even if the client has no transactions
+    /// yet. Freezing a locked account has no effect.
+    pub fn freeze(&mut self, id: ClientId) -> ProcessOutcome {
+        self.clients
+            .entry(id)
+            .or_insert_with(|| Client::new(id))
+            .locked = true;
+        ProcessOutcome::Applied
+    }
+
+    /// Unlock a client's account, locked by a chargeback or a freeze
+    pub fn unfreeze(&mut self, id: ClientId) -> ProcessOutcome {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.locked = false;
+        }
+        ProcessOutcome::Applied
+    }
+
+    /// Get a client's available funds after each of its applied transactions, oldest first
+    ///
+    /// The history is only recorded if enabled with `EngineBuilder::track_history`, otherwise
+    /// it is empty.
+    pub fn history(&self, id: ClientId) -> &[Decimal] {
+        self.history.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Check whether a client exists, even if all its balances are zero
+    pub fn has_client(&self, id: ClientId) -> bool {
+        self.clients.contains_key(&id)
+    }
+
+    /// Get the current state of a client
+    pub fn client(&self, id: ClientId) -> Option<Client> {
+        self.clients.get(&id).copied()
+    }
+
+    /// Capture the engine's clients, transactions and disputes
+    pub fn snapshot(&self) -> EngineSnapshot {
+        EngineSnapshot {
+            clients: self.clients().collect(),
+            transactions: self.transactions.clone(),
+            disputes: self.disbutes.clone(),
+        }
+    }
+
+    /// Create an engine with the default configuration from a snapshot
+    pub fn restore(snapshot: EngineSnapshot) -> Engine {
+        let mut engine = Engine::new();
+        engine.clients = snapshot
+            .clients
+            .into_iter()
+            .map(|client| (client.id, client))
+            .collect();
+        engine.transactions = snapshot.transactions;
+        engine.disbutes = snapshot.disputes;
+        engine
+    }
+
+    /// Remove all clients, transactions and disputes, keeping the configuration and allocations
+    pub fn clear(&mut self) {
+        self.clients.clear();
+        self.transactions.clear();
+        self.disbutes.clear();
+        self.last_tx.clear();
+        self.history.clear();
+        self.totals = Totals::default();
+        self.max_tx = None;
+    }
+
+    /// Merge the state of another engine into this one
+    ///
+    /// Balances of clients present in both engines are summed and the client is locked if it was
+    /// locked in either. Clients only present in `other` are imported as is. If both engines
+    /// processed a transaction with the same id, its amount is already part of both engines'
+    /// balances and totals, so the engines are not merged and this engine is left unchanged. The
+    /// same applies if a summed balance would overflow.
+    pub fn merge(&mut self, other: Engine) -> Result<(), EngineError> {
+        if let Some((&tx, record)) = other
+            .transactions
+            .iter()
+            .find(|(tx, _)| self.transactions.contains_key(tx))
+        {
+            return Err(EngineError::DuplicateTransaction {
+                client: record.client,
+                tx,
+            });
+        }
+        let mut merged = Vec::with_capacity(other.clients.len());
+        for (id, theirs) in other.clients {
+            let ours = self.clients.get(&id).copied().unwrap_or(Client::new(id));
+            match (
+                ours.available.checked_add(theirs.available),
+                ours.held.checked_add(theirs.held),
+                ours.total.checked_add(theirs.total),
+            ) {
+                (Some(available), Some(held), Some(total)) => merged.push(Client {
+                    available,
+                    held,
+                    total,
+                    locked: ours.locked || theirs.locked,
+                    counts: TransactionCounts {
+                        deposits: ours.counts.deposits + theirs.counts.deposits,
+                        withdrawals: ours.counts.withdrawals + theirs.counts.withdrawals,
+                        disputes: ours.counts.disputes + theirs.counts.disputes,
+                    },
+                    scale: ours.scale.or(theirs.scale),
+                    ..ours
+                }),
+                _ => return Err(EngineError::MergeOverflow { client: id }),
+            }
+        }
+        self.clients
+            .extend(merged.into_iter().map(|client| (client.id, client)));
+        self.max_tx = self.max_tx.max(other.max_tx);
+        self.totals.add(other.totals);
+        for (id, (tx, entry)) in other.last_tx {
+            // Entries of `other` follow this engine's entries once the histories are joined
+            let offset = self.history.get(&id).map_or(0, Vec::len);
+            self.last_tx
+                .entry(id)
+                .or_insert((tx, entry.map(|index| index + offset)));
+        }
+        for (id, history) in other.history {
+            self.history.entry(id).or_default().extend(history);
+        }
+        self.transactions.extend(other.transactions);
+        self.disbutes.extend(other.disbutes);
+        Ok(())
+    }
+
+    /// Check that every client's total funds equal its available and held funds
+    pub fn verify_invariants(&self) -> bool {
+        self.clients.values().all(Client::is_consistent)
+    }
+
+    /// Check whether a transaction is currently under dispute
+    pub fn is_disputed(&self, tx: u32) -> bool {
+        self.disbutes.contains(&tx)
+    }
+
+    /// Get the client, id and amount of every transaction under dispute, in ascending id order
+    pub fn open_disputes(&self) -> Vec<(ClientId, u32, Decimal)> {
+        let mut disputes: Vec<(ClientId, u32, Decimal)> = self
+            .disbutes
+            .iter()
+            .filter_map(|tx| {
+                let record = self.transactions.get(tx)?;
+                Some((record.client, *tx, record.amount))
+            })
+            .collect();
+        disputes.sort_unstable_by_key(|&(_, tx, _)| tx);
+        disputes
+    }
+
+    /// Get the amount recorded for a processed deposit or withdrawal
+    pub fn transaction_amount(&self, tx: u32) -> Option<Decimal> {
+        self.transactions.get(&tx).map(|record| record.amount)
+    }
+
+    /// Get the number of transactions of each type applied to a client
+    pub fn transaction_counts(&self, id: ClientId) -> Option<TransactionCounts> {
+        self.clients.get(&id).map(|client| client.counts)
+    }
+
+    /// Compute aggregate statistics over all clients
+    ///
+    /// The deposited, withdrawn and charged back amounts are accumulated as transactions are
+    /// applied, so they only cover transactions processed since the engine was created or
+    /// cleared, not balances loaded with `with_clients` or `restore`.
+    pub fn stats(&self) -> EngineStats {
+        let mut stats = EngineStats {
+            open_disputes: self.disbutes.len(),
+            deposited: self.totals.deposited,
+            withdrawn: self.totals.withdrawn,
+            charged_back: self.totals.charged_back,
+            ..EngineStats::default()
+        };
+        for client in self.clients.values() {
+            stats.clients += 1;
+            stats.locked += usize::from(client.locked);
+            stats.total = stats.total.saturating_add(client.total);
+            stats.held = stats.held.saturating_add(client.held);
+        }
+        stats
+    }
+
+    /// Estimate the number of bytes held by the engine's maps
+    ///
+    /// Counts the allocated capacity of the client, transaction, dispute and history maps, not
+    /// the exact heap usage, which also depends on the hash table layout and the allocator.
+    pub fn memory_footprint(&self) -> usize {
+        fn map<K, V>(map: &HashMap<K, V>) -> usize {
+            map.capacity() * (mem::size_of::<K>() + mem::size_of::<V>())
+        }
+        let history: usize = self
+            .history
+            .values()
+            .map(|history| history.capacity() * mem::size_of::<Decimal>())
+            .sum();
+        map(&self.clients)
+            + map(&self.transactions)
+            + self.disbutes.capacity() * mem::size_of::<u32>()
+            + map(&self.last_tx)
+            + map(&self.history)
+            + history
+    }
+
+    /// Iterate over all clients in ascending id order
+    pub fn clients(&self) -> impl Iterator<Item = Client> + '_ {
+        let mut clients: Vec<Client> = self.clients.values().copied().collect();
+        clients.sort_unstable_by_key(|client| client.id);
+        clients.into_iter()
+    }
+
+    /// Check whether two engines have the same clients with the same state
+    ///
+    /// Balances, lock state and transaction counts are compared, while the transactions,
+    /// disputes and configuration of the engines are not. Balances are compared by value, so
+    /// `1.0` equals `1.00`, but the scale recorded with `EngineBuilder::fixed_client_scale` is
+    /// compared as well, so clients whose first amounts were `1.0` and `1.00` are unequal.
+    pub fn clients_equal(&self, other: &Engine) -> bool {
+        self.clients.len() == other.clients.len() && self.clients().eq(other.clients())
+    }
+
+    /// Get all locked clients in ascending id order
+    pub fn locked_clients(&self) -> Vec<Client> {
+        self.clients().filter(|client| client.locked).collect()
+    }
+
+    /// Write the client list as a json array to a writer, in ascending id order
+    pub fn write_clients_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let clients: Vec<ClientOutput> = self
+            .clients()
+            .map(|client| ClientOutput::new(&client, self.precision))
+            .collect();
+        serde_json::to_writer(&mut *w, &clients)?;
+        w.flush()
+    }
+
+    #[cfg(test)]
+    pub fn get_disputes(&self) -> &HashSet<u32> {
+        &self.disbutes
+    }
+
+    #[cfg(test)]
+    pub fn get_transactions(&self) -> &HashMap<u32, TransactionRecord> {
+        &self.transactions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn build_without_default_features() {
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        let status = std::process::Command::new(env!("CARGO"))
+            .args(["check", "--lib", "--quiet", "--no-default-features"])
+            .arg("--target-dir")
+            .arg(std::path::Path::new(manifest_dir).join("target/no-default-features"))
+            .current_dir(manifest_dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn display_transaction() {
+        let formatted = [
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Withdrawal(2, 3, dec!(0.12345)),
+            Transaction::Dispute(1, 1),
+            Transaction::Resolve(1, 1),
+            Transaction::Chargeback(1, 1),
+            Transaction::Settle(1, 1),
+            Transaction::Freeze(1),
+            Transaction::Unfreeze(2),
+        ]
+        .map(|tx| tx.to_string());
+
+        assert_eq!(
+            formatted,
+            [
+                "deposit client=1 tx=1 amount=1.0000",
+                "withdrawal client=2 tx=3 amount=0.12345",
+                "dispute client=1 tx=1",
+                "resolve client=1 tx=1",
+                "chargeback client=1 tx=1",
+                "settle client=1 tx=1",
+                "freeze client=1",
+                "unfreeze client=2",
+            ]
+        );
+    }
+
+    #[test]
+    fn client() {
+        let mut engine = Engine::new();
+        assert!(engine.client(1).is_none());
+
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(1.5)));
+        let client = engine.client(1).unwrap();
+        assert_eq!(client.id, 1);
+        assert_eq!(client.available, dec!(1.5));
+        assert_eq!(client.held, dec!(0.0));
+        assert_eq!(client.total, dec!(1.5));
+        assert!(!client.locked);
+        assert!(engine.client(2).is_none());
+    }
+
+    #[test]
+    fn has_client() {
+        let mut engine = Engine::new();
+        assert!(!engine.has_client(1));
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Withdrawal(1, 2, dec!(1.0)),
+        ]);
+        assert!(engine.has_client(1));
+        assert!(engine.client(1).unwrap().is_empty());
+        assert!(!engine.has_client(2));
+
+        assert_eq!(
+            engine.handle_record(Transaction::Withdrawal(2, 3, dec!(1.0))),
+            ProcessOutcome::RejectedInsufficientFunds
+        );
+        assert!(!engine.has_client(2));
+    }
+
+    #[test]
+    fn with_clients() {
+        let mut client = Client::new(1);
+        client.available = dec!(100.0);
+        client.total = dec!(100.0);
+        let mut engine = Engine::with_clients(vec![client]);
+
+        engine.handle_all(&[
+            Transaction::Dispute(1, 1),
+            Transaction::Deposit(1, 2, dec!(10.0)),
+            Transaction::Dispute(1, 2),
+        ]);
+
+        assert!(engine.is_disputed(2));
+        assert!(!engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().available == dec!(100.0));
+        assert!(engine.client(1).unwrap().held == dec!(10.0));
+        assert!(engine.client(1).unwrap().total == dec!(110.0));
+    }
+
+    #[test]
+    fn transaction_counts() {
+        let mut engine = Engine::new();
+        assert!(engine.transaction_counts(1).is_none());
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Deposit(1, 2, dec!(5.0)),
+            Transaction::Withdrawal(1, 3, dec!(1.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Resolve(1, 1),
+            Transaction::Dispute(1, 2),
+            Transaction::Deposit(1, 4, dec!(5.0)),
+        ];
+
+        engine.handle_all(&records);
+
+        assert_eq!(
+            engine.transaction_counts(1),
+            Some(TransactionCounts {
+                deposits: 3,
+                withdrawals: 1,
+                disputes: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Deposit(1, 2, dec!(1.0)),
+            Transaction::Deposit(2, 3, dec!(3.0)),
+            Transaction::Deposit(3, 4, dec!(2.5)),
+            Transaction::Dispute(1, 2),
+            Transaction::Chargeback(1, 2),
+            Transaction::Dispute(2, 3),
+        ]);
+
+        let stats = engine.stats();
+        assert_eq!(stats.clients, 3);
+        assert_eq!(stats.locked, 1);
+        assert!(stats.total == dec!(10.5));
+        assert_eq!(stats.open_disputes, 1);
+    }
+
+    #[test]
+    fn stats_saturate() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, Decimal::MAX),
+            Transaction::Deposit(2, 2, Decimal::MAX),
+            Transaction::Dispute(1, 1),
+            Transaction::Dispute(2, 2),
+        ]);
+
+        let stats = engine.stats();
+        assert!(stats.total == Decimal::MAX);
+        assert!(stats.held == Decimal::MAX);
+        assert!(stats.deposited == Decimal::MAX);
+    }
+
+    #[test]
+    fn memory_footprint() {
+        let mut engine = Engine::new();
+        let empty = engine.memory_footprint();
+
+        let txs: Vec<Transaction> = (0..1000)
+            .map(|tx| Transaction::Deposit(tx % 10, tx, dec!(1.0)))
+            .collect();
+        engine.handle_all(&txs);
+        let footprint = engine.memory_footprint();
+        assert!(footprint > empty);
+        assert!(footprint >= 1000 * mem::size_of::<TransactionRecord>());
+
+        engine.clear();
+        assert!(engine.memory_footprint() <= footprint);
+    }
+
+    #[test]
+    fn stats_totals() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Deposit(1, 2, dec!(1.5)),
+            Transaction::Deposit(2, 3, dec!(3.0)),
+            Transaction::Withdrawal(1, 4, dec!(2.0)),
+            Transaction::Withdrawal(2, 5, dec!(10.0)),
+            Transaction::Dispute(1, 2),
+            Transaction::Chargeback(1, 2),
+            Transaction::Dispute(2, 3),
+            Transaction::Deposit(2, 6, dec!(0.25)),
+        ]);
+        assert!(engine.undo_last(2));
+
+        let stats = engine.stats();
+        assert!(stats.deposited == dec!(9.5));
+        assert!(stats.withdrawn == dec!(2.0));
+        assert!(stats.held == dec!(3.0));
+        assert!(stats.charged_back == dec!(1.5));
+    }
+
+    #[test]
+    fn clear() {
+        let mut engine = Engine::new();
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Dispute(1, 1),
+        ]);
+        engine.clear();
+        assert!(engine.clients().next().is_none());
+        assert!(!engine.is_disputed(1));
+
+        engine.handle_all(&[
+            Transaction::Deposit(2, 1, dec!(3.0)),
+            Transaction::Withdrawal(2, 2, dec!(1.0)),
+        ]);
+
+        let clients: Vec<Client> = engine.clients().collect();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].id, 2);
+        assert!(clients[0].total == dec!(2.0));
+        assert_eq!(engine.get_transactions()[&1].client, 2);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Deposit(2, 2, dec!(3.0)),
+            Transaction::Withdrawal(1, 3, dec!(1.5)),
+            Transaction::Dispute(2, 2),
+        ]);
+
+        let json = serde_json::to_string(&engine.snapshot()).unwrap();
+        let mut restored = Engine::restore(serde_json::from_str(&json).unwrap());
+
+        let mut expected = Vec::new();
+        let mut actual = Vec::new();
+        engine.write_clients_json(&mut expected).unwrap();
+        restored.write_clients_json(&mut actual).unwrap();
+        assert_eq!(expected, actual);
+
+        assert!(restored.is_disputed(2));
+        restored.handle_record(Transaction::Resolve(2, 2));
+        assert!(restored.client(2).unwrap().available == dec!(3.0));
+        restored.handle_record(Transaction::Dispute(1, 1));
+        assert!(restored.client(1).unwrap().held == dec!(5.0));
+    }
+
+    #[test]
+    fn merge() {
+        let mut first = Engine::new();
+        first.handle_record(Transaction::Deposit(1, 1, dec!(2.0)));
+        first.handle_record(Transaction::Deposit(2, 2, dec!(3.0)));
+        first.handle_record(Transaction::Dispute(2, 2));
+
+        let mut second = Engine::new();
+        second.handle_record(Transaction::Deposit(3, 3, dec!(4.0)));
+        second.handle_record(Transaction::Deposit(4, 4, dec!(5.0)));
+        second.handle_record(Transaction::Dispute(4, 4));
+        second.handle_record(Transaction::Chargeback(4, 4));
+
+        first.merge(second).unwrap();
+
+        let clients: Vec<Client> = first.clients().collect();
+        assert_eq!(clients.len(), 4);
+        assert!(first.client(1).unwrap().total == dec!(2.0));
+        assert!(first.client(2).unwrap().held == dec!(3.0));
+        assert!(first.client(3).unwrap().available == dec!(4.0));
+        assert!(first.client(4).unwrap().total == dec!(0.0));
+        assert!(first.client(4).unwrap().locked);
+        assert!(first.get_disputes().contains(&2));
+
+        first.resolve(2, 2);
+        assert!(first.client(2).unwrap().available == dec!(3.0));
+        first.dispute(3, 3);
+        assert!(first.client(3).unwrap().held == dec!(4.0));
+    }
+
+    #[test]
+    fn merge_shared_client() {
+        let mut first = Engine::new();
+        first.handle_record(Transaction::Deposit(1, 1, dec!(2.0)));
+
+        let mut second = Engine::new();
+        second.handle_record(Transaction::Deposit(1, 2, dec!(3.0)));
+        second.handle_record(Transaction::Dispute(1, 2));
+
+        first.merge(second).unwrap();
+
+        let client = first.client(1).unwrap();
+        assert!(client.available == dec!(2.0));
+        assert!(client.held == dec!(3.0));
+        assert!(client.total == dec!(5.0));
+        assert_eq!(client.counts.deposits, 2);
+        assert!(!client.locked);
+    }
+
+    #[test]
+    fn merge_duplicate_tx() {
+        let mut first = Engine::new();
+        first.handle_record(Transaction::Deposit(1, 5, dec!(1.0)));
+
+        let mut second = Engine::new();
+        second.handle_all(&[
+            Transaction::Deposit(2, 5, dec!(3.0)),
+            Transaction::Dispute(2, 5),
+            Transaction::Deposit(3, 6, dec!(2.0)),
+        ]);
+
+        assert!(matches!(
+            first.merge(second),
+            Err(EngineError::DuplicateTransaction { client: 2, tx: 5 })
+        ));
+        assert!(first.client(2).is_none());
+        assert!(first.client(3).is_none());
+        assert!(!first.is_disputed(5));
+        assert_eq!(first.transaction_amount(5), Some(dec!(1.0)));
+        let stats = first.stats();
+        assert!(stats.deposited == dec!(1.0));
+        assert!(stats.held == dec!(0.0));
+    }
+
+    #[test]
+    fn merge_overflow() {
+        let mut first = Engine::new();
+        first.handle_record(Transaction::Deposit(1, 1, Decimal::MAX));
+        let mut second = Engine::new();
+        second.handle_all(&[
+            Transaction::Deposit(1, 2, dec!(1)),
+            Transaction::Deposit(2, 3, dec!(1)),
+        ]);
+
+        assert!(matches!(
+            first.merge(second),
+            Err(EngineError::MergeOverflow { client: 1 })
+        ));
+        assert!(first.client(1).unwrap().total == Decimal::MAX);
+        assert!(first.client(2).is_none());
+        assert_eq!(first.transaction_amount(2), None);
+    }
+
+    #[test]
+    fn clients() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(3, 1, dec!(3.0)),
+            Transaction::Deposit(1, 2, dec!(1.0)),
+            Transaction::Deposit(2, 3, dec!(2.0)),
+        ];
+
+        engine.handle_all(&records);
+
+        let clients: Vec<Client> = engine.clients().collect();
+        assert_eq!(clients.len(), 3);
+        for (i, client) in clients.iter().enumerate() {
+            assert_eq!(client.id as usize, i + 1);
+            assert_eq!(client.total, Decimal::from(i + 1));
+        }
+    }
+
+    #[test]
+    fn locked_clients() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(3, 1, dec!(1.0)),
+            Transaction::Deposit(1, 2, dec!(2.0)),
+            Transaction::Deposit(2, 3, dec!(3.0)),
+            Transaction::Dispute(3, 1),
+            Transaction::Chargeback(3, 1),
+            Transaction::Dispute(1, 2),
+            Transaction::Chargeback(1, 2),
+            Transaction::Dispute(2, 3),
+        ]);
+
+        let locked: Vec<ClientId> = engine.locked_clients().iter().map(|c| c.id).collect();
+        assert_eq!(locked, [1, 3]);
+    }
+
+    #[test]
+    fn write_clients_json() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(2, 1, dec!(3.0)),
+            Transaction::Deposit(1, 2, dec!(1.23456)),
+            Transaction::Dispute(2, 1),
+            Transaction::Chargeback(2, 1),
+        ]);
+
+        let mut out = Vec::new();
+        engine.write_clients_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"[{"id":1,"available":"1.2346","held":"0.0000","total":"1.2346","locked":false},"#,
+                r#"{"id":2,"available":"0.0000","held":"0.0000","total":"0.0000","locked":true}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn exact_accumulation() {
+        let mut engine = Engine::new();
+
+        for tx in 0..10000 {
+            engine.handle_record(Transaction::Deposit(1, tx, dec!(0.0001)));
+        }
+
+        assert!(engine.client(1).unwrap().available == dec!(1.0));
+        assert!(engine.client(1).unwrap().total == dec!(1.0));
+        assert_eq!(
+            engine.client(1).unwrap().to_string(),
+            "1, 1.0000, 0.0000, 1.0000, false"
+        );
+    }
+
+    #[test]
+    fn round_half_to_even() {
+        assert_eq!(format_amount(dec!(0.12345), 4), "0.1234");
+        assert_eq!(format_amount(dec!(0.12355), 4), "0.1236");
+        assert_eq!(
+            format_amount(Decimal::MAX, 4),
+            "79228162514264337593543950335.0000"
+        );
+        assert_eq!(format_amount(dec!(-1.5), 2), "-1.50");
+    }
+
+    #[test]
+    fn display_at_scale() {
+        let mut engine = Engine::builder().scale(8).build();
+        let tx = Transaction::Deposit(1, 1, dec!(0.12345678));
+        engine.handle_record(tx);
+
+        assert_eq!(tx.to_string(), "deposit client=1 tx=1 amount=0.12345678");
+        assert_eq!(
+            engine.client(1).unwrap().to_string(),
+            "1, 0.12345678, 0.0000, 0.12345678, false"
+        );
+    }
+
+    #[test]
+    fn negative_zero() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Withdrawal(1, 1, dec!(0.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Resolve(1, 1),
+        ]);
+        assert_eq!(
+            engine.client(1).unwrap().to_string(),
+            "1, 0.0000, 0.0000, 0.0000, false"
+        );
+
+        let mut client = Client::new(2);
+        client.available = dec!(-0.0);
+        client.held = -Decimal::ZERO;
+        assert_eq!(client.to_string(), "2, 0.0000, 0.0000, 0.0000, false");
+    }
+
+    #[test]
+    fn audit_log() {
+        #[derive(Clone, Default)]
+        struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let log = Shared::default();
+        let mut engine = Engine::new();
+        engine.set_audit_log(Box::new(log.clone()));
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Withdrawal(1, 2, dec!(2.0)),
+            Transaction::Dispute(1, 3),
+        ]);
+
+        let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "deposit client=1 tx=1 amount=1.0000: applied",
+                "withdrawal client=1 tx=2 amount=2.0000: rejected, insufficient funds for withdrawal 2 by client 1",
+                "dispute client=1 tx=3: ignored, unknown transaction",
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_record_outcome() {
+        let mut engine = Engine::new();
+
+        assert_eq!(
+            engine.handle_record(Transaction::Deposit(1, 1, dec!(2.0))),
+            ProcessOutcome::Applied
+        );
+        assert_eq!(
+            engine.handle_record(Transaction::Withdrawal(1, 2, dec!(3.0))),
+            ProcessOutcome::RejectedInsufficientFunds
+        );
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 3)),
+            ProcessOutcome::UnknownTx
+        );
+        assert_eq!(
+            engine.handle_record(Transaction::Deposit(1, 1, dec!(1.0))),
+            ProcessOutcome::Duplicate
+        );
+        assert_eq!(
+            engine.handle_record(Transaction::Resolve(1, 1)),
+            ProcessOutcome::NotDisputed
+        );
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn on_record() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let applied = Arc::new(AtomicUsize::new(0));
+        let rejected = Arc::new(AtomicUsize::new(0));
+        let mut engine = Engine::new();
+        engine.on_record({
+            let applied = Arc::clone(&applied);
+            let rejected = Arc::clone(&rejected);
+            move |_, outcome| {
+                let counter = match outcome {
+                    ProcessOutcome::Applied => &applied,
+                    _ => &rejected,
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Withdrawal(1, 2, dec!(5.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Dispute(1, 9),
+        ]);
+
+        assert_eq!(applied.load(Ordering::Relaxed), 2);
+        assert_eq!(rejected.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn handle_record() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Withdrawal(1, 2, dec!(1.0)),
+            Transaction::Deposit(1, 3, dec!(2.0)),
+        ];
+
+        engine.handle_record(records[0]);
+        assert!(engine.client(1).unwrap().available == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+
+        engine.handle_record(records[1]);
+        assert!(engine.client(1).unwrap().available == dec!(1.0));
+        assert!(engine.client(1).unwrap().total == dec!(1.0));
+
+        engine.handle_record(records[2]);
+        assert!(engine.client(1).unwrap().available == dec!(3.0));
+        assert!(engine.client(1).unwrap().total == dec!(3.0));
+    }
+
+    #[test]
+    fn warn_out_of_order() {
+        test_logger::init();
+        let mut engine = Engine::builder().warn_out_of_order(true).build();
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 5501, dec!(1.0)),
+            Transaction::Deposit(1, 5503, dec!(1.0)),
+            Transaction::Deposit(1, 5502, dec!(1.0)),
+        ]);
+
+        assert!(engine.client(1).unwrap().total == dec!(3.0));
+        assert!(!test_logger::logged("Transaction 5503 is lower"));
+        assert!(test_logger::logged(
+            "WARN Transaction 5502 is lower than the previous maximum id 5503"
+        ));
+    }
+
+    #[test]
+    fn undo_last() {
+        let mut engine = Engine::new();
+        assert!(!engine.undo_last(1));
+
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(2.5)));
+        assert!(engine.undo_last(1));
+        assert!(engine.client(1).unwrap().available == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(0.0));
+        assert_eq!(engine.transaction_counts(1).unwrap().deposits, 0);
+        assert!(!engine.get_transactions().contains_key(&1));
+        assert!(!engine.undo_last(1));
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 2, dec!(3.0)),
+            Transaction::Withdrawal(1, 3, dec!(1.0)),
+        ]);
+        assert!(engine.undo_last(1));
+        assert!(engine.client(1).unwrap().available == dec!(3.0));
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 4, dec!(1.0)),
+            Transaction::Dispute(1, 4),
+        ]);
+        assert!(!engine.undo_last(1));
+        assert!(engine.client(1).unwrap().total == dec!(4.0));
+
+        let mut engine = Engine::builder().track_history(true).build();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Deposit(1, 2, dec!(1.0)),
+            Transaction::Withdrawal(1, 3, dec!(2.0)),
+            Transaction::Dispute(1, 2),
+            Transaction::Resolve(1, 2),
+        ]);
+        assert_eq!(
+            engine.history(1),
+            [dec!(5.0), dec!(6.0), dec!(4.0), dec!(3.0), dec!(4.0)]
+        );
+        assert!(engine.undo_last(1));
+        assert_eq!(
+            engine.history(1),
+            [dec!(5.0), dec!(6.0), dec!(5.0), dec!(6.0)]
+        );
+        assert_eq!(
+            engine.history(1).last(),
+            Some(&engine.client(1).unwrap().available)
+        );
+    }
+
+    #[test]
+    fn undo_last_after_merge() {
+        let mut first = Engine::new();
+        first.handle_record(Transaction::Deposit(1, 5, dec!(1.0)));
+        let mut second = Engine::new();
+        second.handle_all(&[
+            Transaction::Deposit(2, 6, dec!(3.0)),
+            Transaction::Withdrawal(2, 7, dec!(0.5)),
+        ]);
+
+        first.merge(second).unwrap();
+
+        assert!(first.undo_last(2));
+        assert!(first.client(2).unwrap().available == dec!(3.0));
+        assert!(!first.undo_last(2));
+        assert_eq!(first.transaction_amount(5), Some(dec!(1.0)));
+        assert!(first.undo_last(1));
+        assert!(first.client(1).unwrap().total == dec!(0.0));
+    }
+
+    #[test]
+    fn history() {
+        let mut engine = Engine::builder().track_history(true).build();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Deposit(2, 2, dec!(1.0)),
+            Transaction::Withdrawal(1, 3, dec!(2.0)),
+            Transaction::Withdrawal(1, 4, dec!(10.0)),
+            Transaction::Deposit(1, 5, dec!(0.5)),
+        ]);
+
+        assert_eq!(engine.history(1), [dec!(5.0), dec!(3.0), dec!(3.5)]);
+        assert_eq!(engine.history(2), [dec!(1.0)]);
+        assert!(engine.history(3).is_empty());
+
+        let mut engine = Engine::new();
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(5.0)));
+        assert!(engine.history(1).is_empty());
+    }
+
+    #[test]
+    fn withdrawal_insufficient_funds() {
+        let mut engine = Engine::new();
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(2.0)));
+
+        let res = engine.transaction(1, 2, TransactionKind::Withdrawal, dec!(3.0));
+        assert!(matches!(
+            res,
+            Err(EngineError::InsufficientFunds { client: 1, tx: 2 })
+        ));
+        assert!(engine.client(1).unwrap().available == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn fixed_client_scale() {
+        test_logger::init();
+        let mut engine = Engine::builder().fixed_client_scale(true).build();
+
+        assert_eq!(
+            engine.handle_record(Transaction::Deposit(1, 921, dec!(10.00))),
+            ProcessOutcome::Applied
+        );
+        assert_eq!(engine.client(1).unwrap().scale, Some(2));
+        assert_eq!(
+            engine.handle_record(Transaction::Withdrawal(1, 922, dec!(1.5))),
+            ProcessOutcome::ScaleMismatch
+        );
+        assert!(test_logger::logged(
+            "ERROR Rejected transaction withdrawal client=1 tx=922 amount=1.5000: \
+             transaction 922 of client 1 has 1 decimal places, expected 2"
+        ));
+        assert_eq!(
+            engine.handle_record(Transaction::Withdrawal(1, 923, dec!(1.50))),
+            ProcessOutcome::Applied
+        );
+        assert!(engine.client(1).unwrap().total == dec!(8.5));
+
+        engine.handle_record(Transaction::Deposit(2, 924, dec!(3)));
+        assert_eq!(engine.client(2).unwrap().scale, Some(0));
+
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(10.00)),
+            Transaction::Withdrawal(1, 2, dec!(1.5)),
+        ]);
+        assert_eq!(engine.client(1).unwrap().scale, None);
+        assert!(engine.client(1).unwrap().total == dec!(8.5));
+    }
+
+    #[test]
+    fn overdraft_policy() {
+        let txs = [
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Withdrawal(1, 2, dec!(3.0)),
+        ];
+
+        let mut engine = Engine::builder()
+            .overdraft_policy(OverdraftPolicy::Reject)
+            .build();
+        engine.handle_all(&txs);
+        assert!(engine.client(1).unwrap().available == dec!(1.0));
+        assert!(engine.client(1).unwrap().total == dec!(1.0));
+
+        let mut engine = Engine::builder()
+            .overdraft_policy(OverdraftPolicy::Allow)
+            .build();
+        assert_eq!(engine.handle_record(txs[0]), ProcessOutcome::Applied);
+        assert_eq!(engine.handle_record(txs[1]), ProcessOutcome::Applied);
+        assert!(engine.client(1).unwrap().available == dec!(-2.0));
+        assert!(engine.client(1).unwrap().total == dec!(-2.0));
+        assert!(engine.verify_invariants());
+    }
+
+    #[test]
+    fn handle_all() {
+        let mut engine = Engine::new();
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Deposit(2, 2, dec!(3.0)),
+            Transaction::Withdrawal(1, 3, dec!(1.5)),
+            Transaction::Dispute(2, 2),
+            Transaction::Chargeback(2, 2),
+        ]);
+
+        assert!(engine.client(1).unwrap().available == dec!(3.5));
+        assert!(engine.client(1).unwrap().total == dec!(3.5));
+        assert!(engine.client(2).unwrap().total == dec!(0.0));
+        assert!(engine.client(2).unwrap().locked);
+    }
+
+    #[test]
+    fn deposit_overflow() {
+        let mut engine = Engine::new();
+
+        let res = engine.transaction(1, 1, TransactionKind::Deposit, Decimal::MAX);
+        assert!(res.is_ok());
+        let res = engine.transaction(1, 2, TransactionKind::Deposit, Decimal::MAX);
+        assert!(matches!(
+            res,
+            Err(EngineError::Overflow { client: 1, tx: 2 })
+        ));
+
+        assert!(engine.client(1).unwrap().available == Decimal::MAX);
+        assert!(engine.client(1).unwrap().total == Decimal::MAX);
+        assert!(!engine.get_transactions().contains_key(&2));
+
+        test_logger::init();
+        assert_eq!(
+            engine.handle_record(Transaction::Deposit(1, 9403, dec!(1))),
+            ProcessOutcome::Overflow
+        );
+        assert!(test_logger::logged(
+            "ERROR Rejected transaction deposit client=1 tx=9403 amount=1.0000: \
+             transaction 9403 would overflow the balance of client 1"
+        ));
+    }
+
+    #[test]
+    fn deposit_overflow_across_clients() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, Decimal::MAX),
+            Transaction::Deposit(2, 2, dec!(1)),
+            Transaction::Dispute(1, 1),
+            Transaction::Chargeback(1, 1),
+            Transaction::Dispute(2, 2),
+            Transaction::Chargeback(2, 2),
+        ]);
+
+        assert!(engine.client(1).unwrap().total == dec!(0));
+        assert!(engine.client(2).unwrap().total == dec!(0));
+        assert!(engine.totals.deposited == Decimal::MAX);
+        assert!(engine.totals.charged_back == Decimal::MAX);
+    }
+
+    #[test]
+    fn duplicate_tx_ignored() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Dispute(1, 1),
+        ];
+
+        engine.handle_all(&records);
+
+        assert!(engine.client(1).unwrap().available == dec!(0.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn duplicate_tx_other_client() {
+        let mut engine = Engine::new();
+
+        engine.handle_record(Transaction::Deposit(1, 7, dec!(2.0)));
+        let res = engine.transaction(2, 7, TransactionKind::Deposit, dec!(3.0));
+        assert!(matches!(
+            res,
+            Err(EngineError::DuplicateTransaction { client: 2, tx: 7 })
+        ));
+
+        assert!(engine.client(2).is_none());
+        assert_eq!(engine.get_transactions()[&7].client, 1);
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn disbute() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Dispute(1, 1),
+        ];
+
+        engine.handle_record(records[0]);
+        engine.handle_record(records[1]);
+
+        assert!(engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(0.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn dispute_spent_deposit() {
+        let mut engine = Engine::new();
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Withdrawal(1, 2, dec!(3.0)),
+            Transaction::Dispute(1, 1),
+        ]);
+
+        assert!(engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().available == dec!(-3.0));
+        assert!(engine.client(1).unwrap().held == dec!(5.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn redispute_ignored() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Dispute(1, 1),
+        ];
+
+        engine.handle_all(&records);
+
+        assert!(engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(0.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn is_disputed() {
+        let mut engine = Engine::new();
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(2.0)));
+        assert!(!engine.is_disputed(1));
+
+        engine.handle_record(Transaction::Dispute(1, 1));
+        assert!(engine.is_disputed(1));
+        assert_eq!(engine.get_transactions()[&1].amount, dec!(2.0));
+
+        engine.handle_record(Transaction::Resolve(1, 1));
+        assert!(!engine.is_disputed(1));
+    }
+
+    #[test]
+    fn warn_not_disputed() {
+        test_logger::init();
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 911, dec!(2.0)),
+            Transaction::Resolve(1, 911),
+            Transaction::Chargeback(1, 911),
+        ]);
+
+        assert!(test_logger::logged(
+            "WARN Ignoring resolve of transaction 911 by client 1, it is not under dispute"
+        ));
+        assert!(test_logger::logged(
+            "WARN Ignoring chargeback of transaction 911 by client 1, it is not under dispute"
+        ));
+        assert!(!engine.client(1).unwrap().locked);
+    }
+
+    #[test]
+    fn max_open_disputes_per_client() {
+        test_logger::init();
+        let mut engine = Engine::builder().max_open_disputes_per_client(1).build();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 901, dec!(2.0)),
+            Transaction::Deposit(1, 902, dec!(1.0)),
+            Transaction::Deposit(2, 903, dec!(1.0)),
+        ]);
+
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 901)),
+            ProcessOutcome::Applied
+        );
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 902)),
+            ProcessOutcome::DisputeLimit
+        );
+        assert!(test_logger::logged(
+            "WARN Ignoring dispute of transaction 902 by client 1, 1 disputes are already open"
+        ));
+        assert!(!engine.is_disputed(902));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(2, 903)),
+            ProcessOutcome::Applied
+        );
+        engine.handle_record(Transaction::Resolve(1, 901));
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 902)),
+            ProcessOutcome::Applied
+        );
+    }
+
+    #[test]
+    fn open_disputes() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(2.5)),
+            Transaction::Deposit(2, 2, dec!(1.0)),
+            Transaction::Withdrawal(1, 3, dec!(0.5)),
+            Transaction::Deposit(2, 4, dec!(3.0)),
+            Transaction::Dispute(2, 4),
+            Transaction::Dispute(1, 3),
+            Transaction::Dispute(2, 2),
+            Transaction::Resolve(2, 2),
+        ]);
+
+        assert_eq!(
+            engine.open_disputes(),
+            [(1, 3, dec!(0.5)), (2, 4, dec!(3.0))]
+        );
+    }
+
+    #[test]
+    fn transaction_amount() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(2.5)),
+            Transaction::Withdrawal(1, 2, dec!(0.5)),
+            Transaction::Withdrawal(1, 3, dec!(5.0)),
+        ]);
+
+        assert!(engine.transaction_amount(1) == Some(dec!(2.5)));
+        assert!(engine.transaction_amount(2) == Some(dec!(0.5)));
+        assert!(engine.transaction_amount(3).is_none());
+        assert!(engine.transaction_amount(4).is_none());
+    }
+
+    #[test]
+    fn resolve() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Resolve(1, 1),
+        ];
+
+        engine.handle_record(records[0]);
+        engine.handle_record(records[1]);
+        engine.handle_record(records[2]);
+
+        assert!(!engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(2.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn resolve_all() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Deposit(1, 2, dec!(3.0)),
+            Transaction::Deposit(2, 3, dec!(4.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Dispute(1, 2),
+            Transaction::Dispute(2, 3),
+        ]);
+
+        assert_eq!(engine.resolve_all(1), 2);
+        assert!(!engine.is_disputed(1));
+        assert!(!engine.is_disputed(2));
+        assert!(engine.is_disputed(3));
+        assert!(engine.client(1).unwrap().available == dec!(5.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert_eq!(engine.resolve_all(1), 0);
+    }
+
+    #[test]
+    fn verify_invariants() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Withdrawal(1, 2, dec!(0.5)),
+            Transaction::Dispute(1, 1),
+            Transaction::Resolve(1, 1),
+            Transaction::Dispute(1, 2),
+            Transaction::Resolve(1, 2),
+        ];
+
+        for record in records {
+            engine.handle_record(record);
+            assert!(engine.verify_invariants());
+        }
+
+        engine.clients.get_mut(&1).unwrap().held = dec!(1.0);
+        assert!(!engine.verify_invariants());
+    }
+
+    #[test]
+    fn chargeback() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Chargeback(1, 1),
+        ];
+
+        engine.handle_record(records[0]);
+        engine.handle_record(records[1]);
+        engine.handle_record(records[2]);
+
+        assert!(!engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(0.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(0.0));
+        assert!(engine.client(1).unwrap().locked);
+    }
+
+    #[test]
+    fn settle() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(5.0)),
+            Transaction::Withdrawal(1, 2, dec!(2.0)),
+            Transaction::Dispute(1, 1),
+        ]);
+
+        assert_eq!(
+            engine.handle_record(Transaction::Settle(1, 1)),
+            ProcessOutcome::Applied
+        );
+        assert!(!engine.is_disputed(1));
+        assert!(engine.client(1).unwrap().available == dec!(3.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(3.0));
+
+        engine.handle_record(Transaction::Dispute(1, 2));
+        engine.handle_record(Transaction::Settle(1, 2));
+        assert!(engine.client(1).unwrap().available == dec!(5.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(5.0));
+        assert!(!engine.client(1).unwrap().locked);
+
+        assert_eq!(
+            engine.handle_record(Transaction::Settle(1, 2)),
+            ProcessOutcome::NotDisputed
+        );
+    }
+
+    #[test]
+    fn freeze() {
+        let mut engine = Engine::new();
+        engine.handle_record(Transaction::Deposit(1, 1, dec!(5.0)));
+
+        assert_eq!(
+            engine.handle_record(Transaction::Freeze(1)),
+            ProcessOutcome::Applied
+        );
+        assert!(engine.client(1).unwrap().locked);
+        assert_eq!(
+            engine.handle_record(Transaction::Deposit(1, 2, dec!(1.0))),
+            ProcessOutcome::Locked
+        );
+
+        assert_eq!(
+            engine.handle_record(Transaction::Unfreeze(1)),
+            ProcessOutcome::Applied
+        );
+        assert!(!engine.client(1).unwrap().locked);
+        engine.handle_record(Transaction::Deposit(1, 2, dec!(1.0)));
+        assert!(engine.client(1).unwrap().total == dec!(6.0));
+
+        engine.handle_record(Transaction::Freeze(2));
+        assert!(engine.client(2).unwrap().locked);
+        engine.handle_record(Transaction::Unfreeze(3));
+        assert!(engine.client(3).is_none());
+    }
+
+    #[test]
+    fn dispute_locked_account() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Deposit(1, 2, dec!(3.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Chargeback(1, 1),
+            Transaction::Dispute(1, 2),
+        ];
+
+        engine.handle_all(&records);
+
+        assert!(!engine.get_disputes().contains(&2));
+        assert!(engine.client(1).unwrap().available == dec!(3.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(3.0));
+        assert!(engine.client(1).unwrap().locked);
+    }
+
+    #[test]
+    fn dispute_no_client() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Dispute(2, 1),
+        ];
+
+        engine.handle_record(records[0]);
+        engine.handle_record(records[1]);
+
+        assert!(!engine.get_disputes().contains(&2));
+        assert!(engine.client(1).unwrap().available == dec!(2.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn dispute_no_tx() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Dispute(1, 2),
+        ];
+
+        engine.handle_record(records[0]);
+        engine.handle_record(records[1]);
+
+        assert!(!engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(2.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn dispute_other_clients_tx() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Deposit(2, 2, dec!(3.0)),
+            Transaction::Dispute(2, 1),
+        ];
+
+        engine.handle_all(&records);
+
+        assert!(!engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(2.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(2).unwrap().available == dec!(3.0));
+        assert!(engine.client(2).unwrap().held == dec!(0.0));
+        assert!(engine.client(2).unwrap().total == dec!(3.0));
+    }
+
+    #[test]
+    fn resolve_other_clients_tx() {
+        let mut engine = Engine::new();
+
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Deposit(2, 2, dec!(3.0)),
+            Transaction::Dispute(1, 1),
+        ]);
+
+        assert_eq!(
+            engine.handle_record(Transaction::Resolve(2, 1)),
+            ProcessOutcome::UnknownTx
+        );
+        assert!(engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(0.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+        assert!(engine.client(2).unwrap().available == dec!(3.0));
+        assert!(engine.client(2).unwrap().held == dec!(0.0));
+        assert!(engine.client(2).unwrap().total == dec!(3.0));
+    }
+
+    #[test]
+    fn dispute_withdrawal() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Withdrawal(1, 2, dec!(1.0)),
+            Transaction::Dispute(1, 2),
+        ];
+
+        engine.handle_all(&records);
+
+        assert!(engine.get_disputes().contains(&2));
+        assert!(engine.client(1).unwrap().available == dec!(1.0));
+        assert!(engine.client(1).unwrap().held == dec!(1.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[test]
+    fn dispute_withdrawal_overflow() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, Decimal::MAX),
+            Transaction::Withdrawal(1, 2, dec!(1)),
+            Transaction::Deposit(1, 3, dec!(1)),
+        ]);
+
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 2)),
+            ProcessOutcome::Overflow
+        );
+        assert_eq!(engine.dispute(1, 2), ProcessOutcome::Overflow);
+        assert!(!engine.is_disputed(2));
+        let client = engine.client(1).unwrap();
+        assert!(client.available == Decimal::MAX);
+        assert!(client.held == dec!(0));
+        assert!(client.total == Decimal::MAX);
+        assert_eq!(client.counts.disputes, 0);
+    }
+
+    #[test]
+    fn disallow_withdrawal_disputes() {
+        test_logger::init();
+        let txs = [
+            Transaction::Deposit(1, 6601, dec!(5.0)),
+            Transaction::Withdrawal(1, 6602, dec!(2.0)),
+        ];
+
+        let mut engine = Engine::new();
+        engine.handle_all(&txs);
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 6602)),
+            ProcessOutcome::Applied
+        );
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+
+        let mut engine = Engine::builder().allow_withdrawal_disputes(false).build();
+        engine.handle_all(&txs);
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 6602)),
+            ProcessOutcome::WithdrawalDispute
+        );
+        assert!(!engine.is_disputed(6602));
+        assert!(engine.client(1).unwrap().available == dec!(3.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(3.0));
+        assert!(test_logger::logged(
+            "WARN Ignoring dispute of withdrawal 6602 by client 1"
+        ));
+
+        assert_eq!(
+            engine.handle_record(Transaction::Dispute(1, 6601)),
+            ProcessOutcome::Applied
+        );
+    }
+
+    #[test]
+    fn chargeback_spent_deposit() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(10.0)),
+            Transaction::Withdrawal(1, 2, dec!(8.0)),
+            Transaction::Dispute(1, 1),
+        ]);
+        assert!(engine.client(1).unwrap().available == dec!(-8.0));
+        assert!(engine.client(1).unwrap().held == dec!(10.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+
+        assert_eq!(
+            engine.handle_record(Transaction::Chargeback(1, 1)),
+            ProcessOutcome::Applied
+        );
+        let client = engine.client(1).unwrap();
+        assert!(client.available == dec!(-8.0));
+        assert!(client.held == dec!(0.0));
+        assert!(client.total == dec!(-8.0));
+        assert!(client.locked);
+        assert!(engine.verify_invariants());
+        assert!(engine.stats().total == dec!(-8.0));
+    }
+
+    #[test]
+    fn resolve_withdrawal() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Withdrawal(1, 2, dec!(1.0)),
+        ]);
+        let before = engine.client(1).unwrap();
+
+        engine.handle_all(&[Transaction::Dispute(1, 2), Transaction::Resolve(1, 2)]);
+
+        let after = engine.client(1).unwrap();
+        assert!(!engine.is_disputed(2));
+        assert!(after.available == before.available);
+        assert!(after.held == before.held);
+        assert!(after.total == before.total);
+        assert!(after.total == dec!(1.0));
+        assert!(!after.locked);
+    }
+
+    #[test]
+    fn chargeback_withdrawal() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Withdrawal(1, 2, dec!(1.0)),
+            Transaction::Dispute(1, 2),
+            Transaction::Chargeback(1, 2),
+        ];
+
+        engine.handle_all(&records);
+
+        assert!(!engine.get_disputes().contains(&2));
+        assert!(engine.client(1).unwrap().available == dec!(2.0));
+        assert!(engine.client(1).unwrap().held == dec!(0.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+        assert!(engine.client(1).unwrap().locked);
+    }
+
+    #[test]
+    fn resolve_no_dispute() {
+        let mut engine = Engine::new();
+
+        let records = [
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Dispute(1, 1),
+            Transaction::Resolve(1, 2),
+        ];
+
+        engine.handle_record(records[0]);
+        engine.handle_record(records[1]);
+        engine.handle_record(records[2]);
+
+        assert!(engine.get_disputes().contains(&1));
+        assert!(engine.client(1).unwrap().available == dec!(0.0));
+        assert!(engine.client(1).unwrap().held == dec!(2.0));
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+}
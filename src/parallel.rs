@@ -0,0 +1,343 @@
+//! Parallel processing of transactions sharded by client, enabled by the `rayon` feature
+
+use std::collections::HashMap;
+use std::mem;
+
+use rayon::prelude::*;
+
+#[cfg(feature = "csv")]
+use crate::csv_io::TimeOrder;
+use crate::{ClientId, Engine, ProcessOutcome, Transaction};
+#[cfg(feature = "csv")]
+use crate::{EngineError, ProcessStats};
+
+impl Engine {
+    /// Handle a batch of transactions, processing clients in parallel
+    ///
+    /// The transactions are partitioned by client id and every shard is processed in order by its
+    /// own thread, after which the shards are merged back into this engine. Disputes can only
+    /// refer to transactions of the same client, so they always find the disputed transaction in
+    /// their shard. The final state is the same as for `handle_all`.
+    ///
+    /// If a transaction id is used by more than one client, an audit log or record callback is set
+    /// or out of order warnings are enabled, the transactions are handled sequentially instead,
+    /// since rejecting the reused id, reporting outcomes in order and detecting decreasing ids all
+    /// depend on the order across clients.
+    pub fn handle_all_parallel(&mut self, txs: &[Transaction]) {
+        self.apply_parallel(txs);
+    }
+
+    /// Read csv records from any reader, processing clients in parallel
+    ///
+    /// All records are parsed before any of them is applied with `handle_all_parallel`. Bad
+    /// records are handled like in `from_reader`, except that in strict mode a record that can
+    /// not be parsed aborts processing before any record is applied. If the input has more than
+    /// `EngineBuilder::max_transactions` records, the records up to the limit are applied before
+    /// the error is returned, as in `from_reader`. If enabled with `EngineBuilder::order_by_time`,
+    /// the records are sorted by their timestamp before parsing.
+    #[cfg(feature = "csv")]
+    pub fn from_reader_parallel<R: std::io::Read>(
+        &mut self,
+        reader: R,
+    ) -> Result<ProcessStats, EngineError> {
+        let mut stats = ProcessStats::default();
+        let mut records = Vec::new();
+        let mut timed = TimeOrder::default();
+        let mut csv_reader = self.csv_reader(reader)?;
+        for row in crate::csv_io::rows(&mut csv_reader)? {
+            let row = match row {
+                Ok(row) => row,
+                Err(EngineError::Csv(err)) => {
+                    self.skip_csv_error(err, &mut stats)?;
+                    continue;
+                }
+                Err(err) => {
+                    self.skip_record(err, &mut stats)?;
+                    continue;
+                }
+            };
+            if row.is_blank() {
+                continue;
+            }
+            if self.order_by_time {
+                timed.push(self, row, &mut stats)?;
+            } else {
+                records.push(row.into_record());
+            }
+        }
+        records.extend(timed.into_sorted());
+        let mut txs = Vec::new();
+        let mut exceeded = None;
+        for record in records {
+            if let Some(limit) = self.max_transactions {
+                if stats.skipped + txs.len() >= limit {
+                    exceeded = Some(EngineError::LimitExceeded { limit });
+                    break;
+                }
+            }
+            match self.parse_record(&record) {
+                Ok(tx) => txs.push(tx),
+                Err(err) if self.strict => return Err(err.into()),
+                Err(err) => {
+                    log::warn!("Skipping record: {}", EngineError::from(err));
+                    stats.skipped += 1;
+                }
+            }
+        }
+        let rejected = self.apply_parallel(&txs);
+        stats.applied += txs.len() - rejected;
+        stats.skipped += rejected;
+        match exceeded {
+            Some(err) => Err(err),
+            None => Ok(stats),
+        }
+    }
+
+    /// Apply transactions sharded by client, returning the number of rejected or ignored
+    /// transactions
+    fn apply_parallel(&mut self, txs: &[Transaction]) -> usize {
+        if self.audit.0.is_some()
+            || self.callback.0.is_some()
+            || self.warn_out_of_order
+            || self.reuses_tx_ids(txs)
+        {
+            return txs.iter().filter(|tx| self.reject(**tx)).count();
+        }
+
+        let shard_count = rayon::current_num_threads().max(1);
+        let shard_of = |id: ClientId| id as usize % shard_count;
+
+        let clients = mem::take(&mut self.clients);
+        let transactions = mem::take(&mut self.transactions);
+        let disputes = mem::take(&mut self.disbutes);
+        let last_tx = mem::take(&mut self.last_tx);
+        let history = mem::take(&mut self.history);
+        let totals = mem::take(&mut self.totals);
+        let mut shards: Vec<(Engine, Vec<Transaction>)> = (0..shard_count)
+            .map(|_| (self.clone(), Vec::new()))
+            .collect();
+        for (id, client) in clients {
+            shards[shard_of(id)].0.clients.insert(id, client);
+        }
+        for (id, tx) in last_tx {
+            shards[shard_of(id)].0.last_tx.insert(id, tx);
+        }
+        for (id, history) in history {
+            shards[shard_of(id)].0.history.insert(id, history);
+        }
+        for (tx, record) in transactions {
+            let shard = &mut shards[shard_of(record.client)].0;
+            shard.transactions.insert(tx, record);
+            if disputes.contains(&tx) {
+                shard.disbutes.insert(tx);
+            }
+        }
+        for tx in txs {
+            shards[shard_of(tx.client())].1.push(*tx);
+        }
+
+        let rejected = shards
+            .par_iter_mut()
+            .map(|(engine, txs)| txs.iter().filter(|tx| engine.reject(**tx)).count())
+            .sum();
+        for (shard, _) in shards {
+            // Shards never share a client, nor a transaction id as reused ids are handled above
+            if let Err(err) = self.merge(shard) {
+                unreachable!("unexpected error merging shards: {}", err);
+            }
+        }
+        self.totals.add(totals);
+        rejected
+    }
+
+    /// Apply a transaction, returning true if it was rejected or ignored and logging rejections
+    fn reject(&mut self, tx: Transaction) -> bool {
+        match self.apply(tx) {
+            Ok(ProcessOutcome::Applied) => false,
+            Ok(_) => true,
+            Err(err) => {
+                log::log!(err.level(), "Rejected transaction {}: {}", tx, err);
+                true
+            }
+        }
+    }
+
+    /// Check if a deposit or withdrawal id is used by more than one client
+    fn reuses_tx_ids(&self, txs: &[Transaction]) -> bool {
+        let mut owners: HashMap<u32, ClientId> = HashMap::new();
+        txs.iter().any(|tx| match *tx {
+            Transaction::Deposit(id, tx, _) | Transaction::Withdrawal(id, tx, _) => {
+                let owner = match self.transactions.get(&tx) {
+                    Some(record) => record.client,
+                    None => *owners.entry(tx).or_insert(id),
+                };
+                owner != id
+            }
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn handle_all_parallel() {
+        let mut txs = Vec::new();
+        for id in 0..50 {
+            let tx = id * 10;
+            txs.push(Transaction::Deposit(id, tx, dec!(10.0)));
+            txs.push(Transaction::Deposit(id, tx + 1, dec!(2.5)));
+            txs.push(Transaction::Withdrawal(id, tx + 2, dec!(4.0)));
+            txs.push(Transaction::Withdrawal(id, tx + 3, dec!(100.0)));
+            match id % 4 {
+                0 => txs.push(Transaction::Dispute(id, tx + 1)),
+                1 => txs.extend([Transaction::Dispute(id, tx), Transaction::Resolve(id, tx)]),
+                2 => txs.extend([
+                    Transaction::Dispute(id, tx + 1),
+                    Transaction::Chargeback(id, tx + 1),
+                    Transaction::Deposit(id, tx + 4, dec!(1.0)),
+                ]),
+                _ => txs.push(Transaction::Dispute(id, tx + 11)),
+            }
+        }
+
+        let mut sequential = Engine::new();
+        sequential.handle_all(&txs);
+        let mut parallel = Engine::new();
+        parallel.handle_all_parallel(&txs);
+
+        assert_eq!(
+            format!("{:?}", parallel.snapshot().clients),
+            format!("{:?}", sequential.snapshot().clients)
+        );
+        assert_eq!(parallel.get_transactions(), sequential.get_transactions());
+        assert_eq!(parallel.get_disputes(), sequential.get_disputes());
+        assert_eq!(parallel.stats(), sequential.stats());
+    }
+
+    #[test]
+    fn handle_all_parallel_reused_tx_id() {
+        let txs = [
+            Transaction::Deposit(1, 1, dec!(1.0)),
+            Transaction::Deposit(2, 1, dec!(2.0)),
+            Transaction::Deposit(2, 2, dec!(3.0)),
+        ];
+
+        let mut engine = Engine::new();
+        engine.handle_all_parallel(&txs);
+
+        assert!(engine.client(1).unwrap().total == dec!(1.0));
+        assert!(engine.client(2).unwrap().total == dec!(3.0));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn from_reader_parallel() {
+        let mut csv = String::from("type, client, tx, amount\n");
+        for tx in 1..=200u32 {
+            let client = tx % 17;
+            csv.push_str(&format!("deposit, {}, {}, {}.5\n", client, tx, tx % 7));
+            if tx % 5 == 0 {
+                csv.push_str(&format!("withdrawal, {}, {}, 3.0\n", client, tx + 1000));
+            }
+            if tx % 9 == 0 {
+                csv.push_str(&format!("dispute, {}, {}\n", client, tx));
+            }
+            if tx % 27 == 0 {
+                csv.push_str(&format!("chargeback, {}, {}\n", client, tx));
+            }
+        }
+        csv.push_str("deposit, 1, x, 1.0\n");
+
+        let mut sequential = Engine::new();
+        let sequential_stats = sequential.from_str(&csv).unwrap();
+        let mut parallel = Engine::new();
+        let parallel_stats = parallel.from_reader_parallel(csv.as_bytes()).unwrap();
+        assert_eq!(parallel_stats, sequential_stats);
+
+        let options = WriteOptions {
+            sorted: true,
+            ..WriteOptions::default()
+        };
+        let mut sequential_out = Vec::new();
+        sequential
+            .write_clients_with(&mut sequential_out, options)
+            .unwrap();
+        let mut parallel_out = Vec::new();
+        parallel
+            .write_clients_with(&mut parallel_out, options)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(parallel_out).unwrap(),
+            String::from_utf8(sequential_out).unwrap()
+        );
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn from_reader_parallel_order_by_time() {
+        let csv = "type, client, tx, amount, timestamp
+withdrawal, 1, 2, 1.0, 2024-01-02T00:00:00Z
+deposit, 1, 1, 3.0, 2024-01-01T00:00:00Z";
+
+        let mut engine = Engine::builder().order_by_time(true).build();
+        let stats = engine.from_reader_parallel(csv.as_bytes()).unwrap();
+        assert_eq!(stats.applied, 2);
+        assert!(engine.client(1).unwrap().total == dec!(2.0));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn from_reader_parallel_ignored_records() {
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+dispute, 1, 99,
+resolve, 1, 1,";
+
+        let mut engine = Engine::new();
+        let stats = engine.from_reader_parallel(csv.as_bytes()).unwrap();
+        assert_eq!(stats.applied, 1);
+        assert_eq!(stats.skipped, 2);
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn from_reader_parallel_limit() {
+        let mut csv = String::from("type, client, tx, amount\n");
+        for tx in 1..=5u32 {
+            csv.push_str(&format!("deposit, {}, {}, 1.0\n", tx % 2, tx));
+        }
+
+        let mut sequential = Engine::builder().max_transactions(3).build();
+        let mut parallel = Engine::builder().max_transactions(3).build();
+        assert!(matches!(
+            sequential.from_str(&csv),
+            Err(EngineError::LimitExceeded { limit: 3 })
+        ));
+        assert!(matches!(
+            parallel.from_reader_parallel(csv.as_bytes()),
+            Err(EngineError::LimitExceeded { limit: 3 })
+        ));
+        assert_eq!(parallel.stats(), sequential.stats());
+        assert!(parallel.stats().total == dec!(3.0));
+    }
+
+    #[test]
+    fn handle_all_parallel_out_of_order() {
+        crate::test_logger::init();
+        let txs = [
+            Transaction::Deposit(1, 7310, dec!(1.0)),
+            Transaction::Deposit(2, 7305, dec!(1.0)),
+        ];
+
+        let mut engine = Engine::builder().warn_out_of_order(true).build();
+        engine.handle_all_parallel(&txs);
+
+        assert!(crate::test_logger::logged(
+            "Transaction 7305 is lower than the previous maximum id 7310"
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+//! A writer duplicating its output to several writers
+
+use std::io::{self, Write};
+
+/// Writes everything to all of its writers, e.g. to a file and stdout
+///
+/// Each write is written completely to every writer in order, so all writers receive the same
+/// output. The first error aborts the write, leaving the later writers behind.
+pub struct Tee<'a> {
+    writers: Vec<&'a mut dyn Write>,
+}
+
+impl<'a> Tee<'a> {
+    pub fn new(writers: Vec<&'a mut dyn Write>) -> Self {
+        Self { writers }
+    }
+}
+
+impl Write for Tee<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for writer in &mut self.writers {
+            writer.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for writer in &mut self.writers {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn write_clients_tee() {
+        let mut engine = Engine::new();
+        engine.handle_all(&[
+            Transaction::Deposit(1, 1, dec!(2.0)),
+            Transaction::Deposit(2, 2, dec!(1.0)),
+            Transaction::Dispute(2, 2),
+        ]);
+
+        let mut first = Vec::new();
+        let mut second = Vec::new();
+        let mut tee = Tee::new(vec![&mut first, &mut second]);
+        engine.write_clients_json(&mut tee).unwrap();
+        #[cfg(feature = "csv")]
+        engine.write_clients(&mut tee).unwrap();
+        drop(tee);
+
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+}
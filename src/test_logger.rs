@@ -0,0 +1,40 @@
+//! A logger capturing log messages so tests can assert on them
+
+use std::sync::{Mutex, Once};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+static INIT: Once = Once::new();
+static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct TestLogger;
+
+impl Log for TestLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let message = format!("{} {}", record.level(), record.args());
+        MESSAGES.lock().unwrap().push(message);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the test logger, tests running in parallel share the captured messages
+pub fn init() {
+    INIT.call_once(|| {
+        log::set_logger(&TestLogger).unwrap();
+        log::set_max_level(LevelFilter::Trace);
+    });
+}
+
+/// Check whether a message containing `needle` has been logged
+pub fn logged(needle: &str) -> bool {
+    MESSAGES
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|message| message.contains(needle))
+}